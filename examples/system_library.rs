@@ -0,0 +1,14 @@
+//! A system library loaded by the `dylib` tests in `tests/system.rs`.
+
+use fecs::{system, SystemRegistrar};
+
+#[system(label = "library")]
+fn library_system(x: &mut i32) {
+    *x += 1;
+}
+
+fn register(registrar: &mut SystemRegistrar) {
+    registrar.add(library_system);
+}
+
+fecs::export_systems!(register);
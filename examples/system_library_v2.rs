@@ -0,0 +1,15 @@
+//! A second version of `system_library.rs`, swapped in
+//! by the `dylib` tests in `tests/system.rs`.
+
+use fecs::{system, SystemRegistrar};
+
+#[system(label = "library")]
+fn library_system(x: &mut i32) {
+    *x += 100;
+}
+
+fn register(registrar: &mut SystemRegistrar) {
+    registrar.add(library_system);
+}
+
+fecs::export_systems!(register);
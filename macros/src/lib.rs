@@ -7,144 +7,691 @@ extern crate quote;
 
 use proc_macro2::{Span, TokenStream};
 use quote::ToTokens;
-use syn::{FnArg, Ident, ItemFn, Pat, PatType, Type};
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, AttributeArgs, Data, DeriveInput, Error, FnArg, GenericArgument, Ident, Index,
+    ItemFn, Lit, Meta, NestedMeta, Pat, PatType, PathArguments, Result, ReturnType, Signature,
+    Type, TypeReference,
+};
+
+/// Describes the parameters accepted by systems and event handlers.
+const ALLOWED_PARAMETERS: &str =
+    "expected a resource (`&T`, `&mut T` or `T: Copy`), an optional resource \
+     (`Option<&T>` or `Option<&mut T>`), `&World`, `&mut World`, \
+     `&mut CommandBuffer`, `&ResourcesEnum` or `&Executor`";
 
 #[proc_macro_attribute]
 pub fn system(
-    _args: proc_macro::TokenStream,
+    args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
     let input: ItemFn = parse_macro_input!(input as ItemFn);
 
+    system_impl(args, input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn system_impl(args: AttributeArgs, input: ItemFn) -> Result<TokenStream> {
+    let SystemArgs {
+        stage,
+        setup: setup_fn,
+        labels,
+        before,
+        after,
+    } = parse_system_args(args)?;
+
     let sig = &input.sig;
-    assert!(
-        sig.generics.params.is_empty(),
-        "systems may not have generic parameters"
-    );
+    check_generics(sig)?;
+
+    let Parameters {
+        resources_init,
+        set_up,
+        world,
+        commands,
+        all_resources,
+        reads,
+        writes,
+        optional,
+        state,
+    } = find_function_parameters(sig.inputs.iter())?;
 
-    let (resources_init, set_up, world_ident) = find_function_parameters(sig.inputs.iter());
+    let sys_name = input.sig.ident.clone();
+    let cfgs = cfg_attrs(&input);
 
-    let (world_ident, world_ty) = world_ident.unwrap_or((
-        Ident::new("_world", Span::call_site()),
-        quote! { &mut fecs::World },
-    ));
+    // Systems returning a `Result` are implemented through `try_run`
+    // (or `try_run_shared`), with `run` logging errors.
+    let fallible = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(ty),
+    };
 
-    let content = &input.block;
+    // Systems which don't need mutable access to the world
+    // are implemented through `run_shared`, so that they can run in parallel.
+    let (world_access, world_ident, world_ty, shared) = match world {
+        Some((world_ident, world_ty, true)) => (
+            quote! { fecs::WorldAccess::Write },
+            world_ident,
+            world_ty,
+            false,
+        ),
+        Some((world_ident, world_ty, false)) => (
+            quote! { fecs::WorldAccess::Read },
+            world_ident,
+            world_ty,
+            true,
+        ),
+        None => (
+            quote! { fecs::WorldAccess::Unused },
+            Ident::new("_world", Span::call_site()),
+            quote! { &fecs::World },
+            true,
+        ),
+    };
 
-    let sys_name = input.sig.ident.clone();
+    // Commands recorded by the system are submitted to the world when it returns.
+    let block = &input.block;
+    let content = match commands {
+        Some(commands_ident) => quote! {{
+            let mut command_buffer = fecs::CommandBuffer::new();
+            let #commands_ident = &mut command_buffer;
+            #[allow(clippy::redundant_closure_call)]
+            let result = (|| #block)();
+            #world_ident.submit_commands(command_buffer);
+            result
+        }},
+        None => block.to_token_stream(),
+    };
 
-    let res = quote! {
-        #[allow(non_camel_case_types)]
-        #[derive(Clone)]
-        pub struct #sys_name;
+    let (run_name, try_run_name) = if shared {
+        (quote! { run_shared }, quote! { try_run_shared })
+    } else {
+        (quote! { run }, quote! { try_run })
+    };
 
-        impl fecs::RawSystem for #sys_name {
-            fn run(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, _executor: &fecs::Executor) {
+    let mut run = match fallible {
+        None => quote! {
+            #[allow(unused_variables)]
+            fn #run_name(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, _executor: &fecs::Executor) {
                 use fecs::ResourcesProvider as _;
                 #(#resources_init)*
                 #content
             }
+        },
+        Some(ret_ty) => quote! {
+            fn #run_name(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, executor: &fecs::Executor) {
+                if let Err(e) = self.#try_run_name(resources, #world_ident, executor) {
+                    fecs::log_system_error(fecs::RawSystem::name(self), &e);
+                }
+            }
+
+            #[allow(unused_variables, clippy::redundant_closure_call)]
+            fn #try_run_name(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, _executor: &fecs::Executor) -> Result<(), fecs::SystemError> {
+                use fecs::ResourcesProvider as _;
+                #(#resources_init)*
+                let result: #ret_ty = (|| #content)();
+                result.map_err(Into::into)
+            }
+        },
+    };
+
+    if shared {
+        run.extend(quote! {
+            fn run(&self, resources: &fecs::ResourcesEnum, world: &mut fecs::World, executor: &fecs::Executor) {
+                self.run_shared(resources, world, executor);
+            }
+
+            fn supports_shared(&self) -> bool {
+                true
+            }
+        });
+        if fallible.is_some() {
+            run.extend(quote! {
+                fn try_run(&self, resources: &fecs::ResourcesEnum, world: &mut fecs::World, executor: &fecs::Executor) -> Result<(), fecs::SystemError> {
+                    self.try_run_shared(resources, world, executor)
+                }
+            });
+        }
+    }
+
+    // Systems taking the `ResourcesEnum` itself may access any resource.
+    let access = if all_resources {
+        quote! { None }
+    } else {
+        quote! {
+            Some(fecs::SystemAccess {
+                resource_reads: vec![#(std::any::TypeId::of::<#reads>()),*],
+                resource_writes: vec![#(std::any::TypeId::of::<#writes>()),*],
+                optional_resources: vec![#(std::any::TypeId::of::<#optional>()),*],
+                world: #world_access,
+            })
+        }
+    };
+
+    let stage = stage.map(|stage| {
+        quote! {
+            fn stage(&self) -> fecs::Stage {
+                fecs::Stage::#stage
+            }
+        }
+    });
+
+    // The setup function is called by `set_up` after `#[default]`
+    // resources have been inserted and state has been initialized.
+    let setup_call = setup_fn.map(|setup_fn| {
+        quote! {
+            #setup_fn(resources, world);
+        }
+    });
+
+    // Systems with `#[state]` parameters store the state in the system struct.
+    // A constant of the same name stands in for the unit struct value,
+    // so that the system can still be referred to by its name. Clones
+    // start out with fresh state.
+    let state_idents: Vec<_> = state.iter().map(|(ident, _)| ident).collect();
+    let state_tys: Vec<_> = state.iter().map(|(_, ty)| ty).collect();
+    let definition = if state.is_empty() {
+        quote! {
+            #(#cfgs)*
+            #[allow(non_camel_case_types)]
+            #[derive(Clone)]
+            pub struct #sys_name;
+        }
+    } else {
+        quote! {
+            #(#cfgs)*
+            #[allow(non_camel_case_types)]
+            pub struct #sys_name {
+                #(#state_idents: std::sync::Mutex<Option<#state_tys>>,)*
+            }
+
+            #(#cfgs)*
+            #[allow(non_upper_case_globals, clippy::declare_interior_mutable_const)]
+            pub const #sys_name: #sys_name = #sys_name {
+                #(#state_idents: std::sync::Mutex::new(None),)*
+            };
+
+            #(#cfgs)*
+            impl Clone for #sys_name {
+                fn clone(&self) -> Self {
+                    #sys_name
+                }
+            }
+        }
+    };
+
+    let res = quote! {
+        #definition
+
+        #(#cfgs)*
+        impl fecs::RawSystem for #sys_name {
+            #run
 
             #[allow(unused_variables)]
-            fn set_up(&mut self, resources: &mut fecs::OwnedResources, #world_ident: #world_ty) {
+            fn set_up(&mut self, resources: &mut fecs::OwnedResources, world: &mut fecs::World) {
                 #(#set_up)*
+                #(
+                    *self.#state_idents.get_mut().unwrap_or_else(std::sync::PoisonError::into_inner) =
+                        Some(Default::default());
+                )*
+                #setup_call
+            }
+
+            fn name(&self) -> &'static str {
+                concat!(module_path!(), "::", stringify!(#sys_name))
             }
+
+            fn ordering(&self) -> fecs::SystemOrdering {
+                fecs::SystemOrdering {
+                    labels: vec![#(#labels),*],
+                    before: vec![#(#before),*],
+                    after: vec![#(#after),*],
+                }
+            }
+
+            fn access(&self) -> Option<fecs::SystemAccess> {
+                #access
+            }
+
+            #stage
         }
     };
 
-    res.into()
+    Ok(res)
 }
 
 #[proc_macro_attribute]
 pub fn event_handler(
-    _args: proc_macro::TokenStream,
+    args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
     let input: ItemFn = parse_macro_input!(input as ItemFn);
 
+    event_handler_impl(args, input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn event_handler_impl(args: AttributeArgs, input: ItemFn) -> Result<TokenStream> {
+    let EventHandlerArgs {
+        setup: setup_fn,
+        priority,
+    } = parse_event_handler_args(args)?;
+
     let sig = &input.sig;
-    assert!(
-        sig.generics.params.is_empty(),
-        "systems may not have generic parameters"
-    );
+    check_generics(sig)?;
 
     // Find whether this is a batch handler or not, based on the first argument, which
     // is the event argument.
-    let event_arg = sig
-        .inputs
-        .first()
-        .expect("event handler must take event as its first parameter");
-    let event_ty = match event_arg {
-        FnArg::Typed(p) => p,
-        _ => panic!("event handler may not take self parameter"),
-    };
-
-    let (_is_batch, event_ty) = match &*event_ty.ty {
+    let event_arg = sig.inputs.first().ok_or_else(|| {
+        Error::new_spanned(
+            sig,
+            "event handlers must take the event as their first parameter, \
+             e.g. `event: &MyEvent`, `event: &mut MyEvent` or `events: &[MyEvent]`",
+        )
+    })?;
+    let event_ty = arg(event_arg)?;
+
+    let event_pat = &event_ty.pat;
+    let (is_batch, is_mut, event_ty) = match &*event_ty.ty {
         Type::Reference(r) => match *r.elem.clone() {
-            Type::Slice(s) => (true, (&*s.elem).clone()),
-            t => (false, t),
+            Type::Slice(s) => (true, r.mutability.is_some(), (&*s.elem).clone()),
+            t => (false, r.mutability.is_some(), t),
         },
-        _ => unimplemented!(),
+        ty => {
+            return Err(Error::new_spanned(
+                ty,
+                "the event must be taken by reference: \
+                 `&MyEvent`, `&mut MyEvent` or `&[MyEvent]`",
+            ))
+        }
     };
+    if is_batch && is_mut {
+        return Err(Error::new_spanned(
+            &event_arg,
+            "batch event handlers may not take their events mutably; use `&[MyEvent]`",
+        ));
+    }
+    if is_batch {
+        if let ReturnType::Type(_, ty) = &sig.output {
+            return Err(Error::new_spanned(
+                ty,
+                "batch event handlers may not return a value",
+            ));
+        }
+    }
+
+    let Parameters {
+        resources_init,
+        set_up,
+        world,
+        commands,
+        executor,
+        state,
+        ..
+    } = find_function_parameters(sig.inputs.iter().skip(1))?;
+    if let Some(executor) = executor {
+        return Err(Error::new_spanned(
+            executor,
+            "event handlers may not take an `Executor` parameter",
+        ));
+    }
+    if let Some((ident, _)) = state.first() {
+        return Err(Error::new_spanned(
+            ident,
+            "event handlers may not take `#[state]` parameters",
+        ));
+    }
+    if let Some(commands) = commands {
+        return Err(Error::new_spanned(
+            commands,
+            "event handlers may not take a `CommandBuffer` parameter; \
+             take `&mut World` to change the world directly",
+        ));
+    }
 
-    let (resources_init, set_up, world_ident) = find_function_parameters(sig.inputs.iter().skip(1));
+    let (world_ident, world_ty) = world
+        .map(|(world_ident, world_ty, _)| (world_ident, world_ty))
+        .unwrap_or((
+            Ident::new("_world", Span::call_site()),
+            quote! { &mut fecs::World },
+        ));
+
+    // The setup function is called by `set_up` after
+    // `#[default]` resources have been inserted.
+    let setup_call = setup_fn.map(|setup_fn| {
+        quote! {
+            #setup_fn(resources, #world_ident);
+        }
+    });
 
-    let (world_ident, world_ty) = world_ident.unwrap_or((
-        Ident::new("_world", Span::call_site()),
-        quote! { &mut fecs::World },
-    ));
+    let priority = priority.map(|priority| {
+        quote! {
+            fn priority(&self) -> i32 {
+                #priority
+            }
+        }
+    });
 
     let sys_name = input.sig.ident.clone();
 
     let content = &input.block;
 
-    let res = quote! {
-        #[allow(non_camel_case_types)]
-        pub struct #sys_name;
+    let output_ty = match &sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => quote! { #ty },
+    };
 
-        impl fecs::RawEventHandler for #sys_name {
-            type Event = #event_ty;
-            fn handle(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, event: &#event_ty) {
+    // Batch handlers handle single events as a batch of one.
+    let handle = if is_batch {
+        quote! {
+            fn handle(&self, resources: &fecs::ResourcesEnum, world: &mut fecs::World, event: &#event_ty) {
+                <Self as fecs::RawEventHandler>::handle_batch(self, resources, world, std::slice::from_ref(event));
+            }
+
+            fn handle_batch(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, #event_pat: &[#event_ty]) {
+                use fecs::ResourcesProvider as _;
+                #(#resources_init)*
+
+                #content
+            }
+        }
+    } else if is_mut {
+        // Shared events, e.g. from `trigger_batched`, are handled through
+        // a clone, so handlers taking `&mut E` require `E: Clone`.
+        let clone = quote_spanned! {event_ty.span()=>
+            <#event_ty as std::clone::Clone>::clone(event)
+        };
+        quote! {
+            fn handle(&self, resources: &fecs::ResourcesEnum, world: &mut fecs::World, event: &#event_ty) -> #output_ty {
+                let mut event = #clone;
+                <Self as fecs::RawEventHandler>::handle_mut(self, resources, world, &mut event)
+            }
+
+            fn handle_mut(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, #event_pat: &mut #event_ty) -> #output_ty {
+                use fecs::ResourcesProvider as _;
+                #(#resources_init)*
+
+                #content
+            }
+        }
+    } else {
+        quote! {
+            fn handle(&self, resources: &fecs::ResourcesEnum, #world_ident: #world_ty, #event_pat: &#event_ty) -> #output_ty {
                 use fecs::ResourcesProvider as _;
                 #(#resources_init)*
 
                 #content
             }
+        }
+    };
+
+    let cfgs = cfg_attrs(&input);
+    let res = quote! {
+        #(#cfgs)*
+        #[allow(non_camel_case_types)]
+        pub struct #sys_name;
+
+        #(#cfgs)*
+        impl fecs::RawEventHandler for #sys_name {
+            type Event = #event_ty;
+            type Output = #output_ty;
+            #handle
 
             #[allow(unused_variables)]
             fn set_up(&mut self, resources: &mut fecs::OwnedResources, #world_ident: #world_ty) {
                 #(#set_up)*
+                #setup_call
             }
+
+            #priority
         }
     };
 
-    res.into()
+    Ok(res)
+}
+
+/// Implements `fecs::Bundle` for a struct, adding each of its fields
+/// to the entity as a component.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    derive_bundle_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn derive_bundle_impl(input: DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`Bundle` can only be derived for structs",
+            ))
+        }
+    };
+
+    let fields = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.to_token_stream(),
+            None => Index::from(i).to_token_stream(),
+        });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics fecs::Bundle for #name #ty_generics #where_clause {
+            fn add_to(self, builder: &mut fecs::EntityBuilder) {
+                #(builder.add(self.#fields);)*
+            }
+        }
+    })
 }
 
-fn find_function_parameters<'a>(
-    inputs: impl Iterator<Item = &'a FnArg>,
-) -> (
-    Vec<TokenStream>,
-    Vec<TokenStream>,
-    Option<(Ident, TokenStream)>,
-) {
-    // Vector of resource takes from the `Resources`.
+/// Returns the `#[cfg]` attributes of the function, which
+/// must be applied to each item generated from it.
+fn cfg_attrs(input: &ItemFn) -> Vec<&Attribute> {
+    input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident(&Ident::new("cfg", Span::call_site())))
+        .collect()
+}
+
+/// Fails if the function has generic parameters.
+fn check_generics(sig: &Signature) -> Result<()> {
+    if sig.generics.params.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new_spanned(
+            &sig.generics,
+            "systems and event handlers may not have generic parameters",
+        ))
+    }
+}
+
+/// The arguments passed to the `event_handler` attribute,
+/// e.g. `#[event_handler(setup = "init_chat", priority = 100)]`.
+#[derive(Default)]
+struct EventHandlerArgs {
+    /// Path to the setup function.
+    setup: Option<syn::Path>,
+    priority: Option<syn::LitInt>,
+}
+
+fn parse_event_handler_args(args: AttributeArgs) -> Result<EventHandlerArgs> {
+    let mut parsed = EventHandlerArgs::default();
+
+    for arg in args {
+        let name_value = match arg {
+            NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+            arg => {
+                return Err(Error::new_spanned(
+                    arg,
+                    "event handler arguments must be of the form `name = value`",
+                ))
+            }
+        };
+
+        match (&name_value.lit, name_value.path.get_ident()) {
+            (Lit::Str(value), Some(name)) if name == "setup" => {
+                parsed.setup = Some(value.parse().map_err(|_| {
+                    Error::new_spanned(value, "`setup` must be a path to a function")
+                })?);
+            }
+            (Lit::Int(value), Some(name)) if name == "priority" => {
+                parsed.priority = Some(value.clone());
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    &name_value,
+                    "unknown event handler argument; \
+                     expected `setup = \"function\"` or `priority = integer`",
+                ))
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// The arguments passed to the `system` attribute,
+/// e.g. `#[system(stage = "post_update", label = "physics", after = "input")]`.
+#[derive(Default)]
+struct SystemArgs {
+    /// The `fecs::Stage` variant, if given.
+    stage: Option<Ident>,
+    /// Function called by `set_up`, given as `setup = "function"`.
+    setup: Option<syn::Path>,
+    labels: Vec<String>,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+fn parse_system_args(args: AttributeArgs) -> Result<SystemArgs> {
+    let mut parsed = SystemArgs::default();
+
+    for arg in args {
+        let name_value = match arg {
+            NestedMeta::Meta(Meta::NameValue(name_value)) => name_value,
+            arg => {
+                return Err(Error::new_spanned(
+                    arg,
+                    "system arguments must be of the form `name = \"value\"`",
+                ))
+            }
+        };
+        let value = match &name_value.lit {
+            Lit::Str(s) => s.value(),
+            lit => {
+                return Err(Error::new_spanned(
+                    lit,
+                    "system argument values must be string literals",
+                ))
+            }
+        };
+        let name = name_value
+            .path
+            .get_ident()
+            .map(Ident::to_string)
+            .unwrap_or_default();
+
+        match name.as_str() {
+            "setup" => {
+                parsed.setup = Some(syn::parse_str(&value).map_err(|_| {
+                    Error::new_spanned(&name_value.lit, "`setup` must be a path to a function")
+                })?);
+            }
+            "label" => parsed.labels.push(value),
+            "before" => parsed.before.push(value),
+            "after" => parsed.after.push(value),
+            "stage" => {
+                let variant = match value.as_str() {
+                    "first" => "First",
+                    "pre_update" => "PreUpdate",
+                    "update" => "Update",
+                    "post_update" => "PostUpdate",
+                    "last" => "Last",
+                    _ => {
+                        return Err(Error::new_spanned(
+                            &name_value.lit,
+                            "unknown stage; expected one of `first`, `pre_update`, \
+                             `update`, `post_update` or `last`",
+                        ))
+                    }
+                };
+                parsed.stage = Some(Ident::new(variant, Span::call_site()));
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    &name_value.path,
+                    "unknown system argument; \
+                     expected `stage`, `setup`, `label`, `before` or `after`",
+                ))
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// The parsed parameters of a system or event handler function.
+struct Parameters {
+    /// Resource takes from the `Resources`.
+    resources_init: Vec<TokenStream>,
+    /// Setup statements.
+    set_up: Vec<TokenStream>,
+    /// Ident and type of the World variable, and whether it is mutable.
+    world: Option<(Ident, TokenStream, bool)>,
+    /// Ident of the `CommandBuffer` variable.
+    commands: Option<Ident>,
+    /// Ident of the `Executor` variable.
+    executor: Option<Ident>,
+    /// Whether the `ResourcesEnum` itself is taken as a parameter.
+    all_resources: bool,
+    /// Types of resources borrowed immutably.
+    reads: Vec<TokenStream>,
+    /// Types of resources borrowed mutably.
+    writes: Vec<TokenStream>,
+    /// Types of resources taken as `Option`s, which may be missing.
+    optional: Vec<TokenStream>,
+    /// Idents and types of `#[state]` parameters, stored in the system.
+    state: Vec<(Ident, TokenStream)>,
+}
+
+fn find_function_parameters<'a>(inputs: impl Iterator<Item = &'a FnArg>) -> Result<Parameters> {
     let mut resources_init = vec![];
-    // Vector of setup statements.
     let mut set_up = vec![];
-    // Vector of resource variable names (`Ident`s).
-    // Ident of the World variable.
-    let mut world_ident = None;
+    let mut world = None;
+    let mut commands = None;
+    let mut executor = None;
+    let mut all_resources = false;
+    let mut reads = vec![];
+    let mut writes = vec![];
+    let mut optional = vec![];
+    let mut state = vec![];
 
     // Parse function arguments and determine whether they refer to resources,
     // the `PreparedWorld`, or the `CommandBuffer`.
     // Note that queries are performed inside the function using `cohort::query`.
     // This is implemented below.
     for param in inputs {
-        let arg = arg(param);
+        let arg = arg(param)?;
         let ident = match &*arg.pat {
             Pat::Ident(ident) => ident.ident.clone(),
-            _ => panic!(),
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "parameters must be plain identifiers, not patterns",
+                ))
+            }
         };
 
         let init_with_default = arg.attrs.iter().any(|attr| {
@@ -152,14 +699,73 @@ fn find_function_parameters<'a>(
                 .is_ident(&Ident::new("default", Span::call_site()))
         });
 
-        let (mutability, ty) = parse_arg(arg);
+        // State is initialized with `Default` when the system is set up,
+        // or on its first run if it never was.
+        let is_state = arg
+            .attrs
+            .iter()
+            .any(|attr| attr.path.is_ident(&Ident::new("state", Span::call_site())));
+        if is_state {
+            let ty = match &*arg.ty {
+                Type::Reference(r) if r.mutability.is_some() => &r.elem,
+                ty => {
+                    return Err(Error::new_spanned(
+                        ty,
+                        "`#[state]` parameters must be mutable references, e.g. `&mut u32`",
+                    ))
+                }
+            };
+            resources_init.push(quote! {
+                let mut #ident = self.#ident.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+                let #ident: &mut #ty = #ident.get_or_insert_with(Default::default);
+            });
+            state.push((ident, ty.to_token_stream()));
+            continue;
+        }
+
+        let (mutability, ty) = parse_arg(arg)?;
 
         match ty {
-            ArgType::World => world_ident = Some((ident, arg.ty.to_token_stream())),
+            ArgType::World => world = Some((ident, arg.ty.to_token_stream(), mutability.is_some())),
+            ArgType::CommandBuffer => {
+                if mutability.is_none() {
+                    return Err(Error::new_spanned(
+                        &arg.ty,
+                        "`CommandBuffer` parameters must be `&mut CommandBuffer`",
+                    ));
+                }
+                commands = Some(ident);
+            }
+            ArgType::Executor => {
+                if mutability.is_some() {
+                    return Err(Error::new_spanned(
+                        &arg.ty,
+                        "`Executor` parameters must be `&Executor`",
+                    ));
+                }
+                resources_init.push(quote! {
+                    let #ident: &fecs::Executor = _executor;
+                });
+                executor = Some(ident);
+            }
+            ArgType::Resources => {
+                if mutability.is_some() {
+                    return Err(Error::new_spanned(
+                        &arg.ty,
+                        "`ResourcesEnum` parameters must be `&ResourcesEnum`",
+                    ));
+                }
+                resources_init.push(quote! {
+                    let #ident: &fecs::ResourcesEnum = resources;
+                });
+                all_resources = true;
+            }
             ArgType::Resource(res) => {
                 let get_fn = if mutability.is_some() {
+                    writes.push(res.clone());
                     quote! { get_mut }
                 } else {
+                    reads.push(res.clone());
                     quote! { get }
                 };
                 let init = quote! {
@@ -174,47 +780,151 @@ fn find_function_parameters<'a>(
                     });
                 }
             }
+            ArgType::CopyResource(res) => {
+                reads.push(res.clone());
+
+                // The resource is copied out so that it isn't borrowed while the system runs.
+                let pat = &arg.pat;
+                resources_init.push(quote! {
+                    let #pat: #res = *resources.get::<#res>();
+                });
+
+                if init_with_default {
+                    set_up.push(quote! {
+                        resources.insert(#res::default());
+                    });
+                }
+            }
+            ArgType::OptionalResource(res) => {
+                let (get_fn, as_deref) = if mutability.is_some() {
+                    writes.push(res.clone());
+                    (quote! { try_get_mut }, quote! { as_deref_mut })
+                } else {
+                    reads.push(res.clone());
+                    (quote! { try_get }, quote! { as_deref })
+                };
+                optional.push(res.clone());
+
+                // Only a missing resource yields `None`; borrow conflicts still panic.
+                let init = quote! {
+                    let #mutability #ident = match resources.#get_fn::<#res>() {
+                        Ok(resource) => Some(resource),
+                        Err(fecs::ResourceError::NotFound(_)) => None,
+                        Err(e) => panic!("{}", e),
+                    };
+                    let #ident: Option<&#mutability #res> = #ident.#as_deref();
+                };
+                resources_init.push(init);
+            }
         }
     }
 
-    (resources_init, set_up, world_ident)
+    Ok(Parameters {
+        resources_init,
+        set_up,
+        world,
+        commands,
+        executor,
+        all_resources,
+        reads,
+        writes,
+        optional,
+        state,
+    })
 }
 
-fn parse_arg(arg: &PatType) -> (Option<Token![mut]>, ArgType) {
+fn parse_arg(arg: &PatType) -> Result<(Option<Token![mut]>, ArgType)> {
+    let invalid = || Error::new_spanned(&arg.ty, ALLOWED_PARAMETERS);
+
+    if let Some(reference) = option_reference(&arg.ty) {
+        let ty = match &*reference.elem {
+            Type::Path(path) => &path.path,
+            _ => return Err(invalid()),
+        };
+        return Ok((
+            reference.mutability,
+            ArgType::OptionalResource(quote! { #ty }),
+        ));
+    }
+
     let arg = match &*arg.ty {
         Type::Reference(r) => r,
-        _ => panic!("Invalid argument type"),
+        // Anything else taken by value must be a `Copy` resource.
+        Type::Path(path) => {
+            let special = path.path.segments.last().map_or(false, |segment| {
+                ["World", "CommandBuffer", "ResourcesEnum", "Executor"]
+                    .iter()
+                    .any(|name| segment.ident == name)
+            });
+            if special {
+                return Err(Error::new_spanned(
+                    path,
+                    "this parameter must be taken by reference",
+                ));
+            }
+            let ty = &path.path;
+            return Ok((None, ArgType::CopyResource(quote! { #ty })));
+        }
+        _ => return Err(invalid()),
     };
 
     let inner = match &*arg.elem {
         Type::Path(path) => path,
-        _ => panic!("Invalid argument type"),
+        _ => return Err(invalid()),
     };
 
-    let ty = inner.path.segments.last().expect("no last path segment");
+    let ty = inner.path.segments.last().ok_or_else(invalid)?;
 
-    let world = "World";
+    let ty = match ty.ident.to_string().as_str() {
+        "World" => ArgType::World,
+        "CommandBuffer" => ArgType::CommandBuffer,
+        "ResourcesEnum" => ArgType::Resources,
+        "Executor" => ArgType::Executor,
+        _ => {
+            let ty = &inner.path;
+            ArgType::Resource(quote! { #ty })
+        }
+    };
 
-    let string = ty.ident.to_string();
+    Ok((arg.mutability, ty))
+}
 
-    let ty = if string == world {
-        ArgType::World
-    } else {
-        let ty = &inner.path;
-        ArgType::Resource(quote! { #ty })
+/// Returns the reference in an `Option<&T>` or `Option<&mut T>` type.
+fn option_reference(ty: &Type) -> Option<&TypeReference> {
+    let path = match ty {
+        Type::Path(path) => path,
+        _ => return None,
     };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
 
-    (arg.mutability, ty)
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(Type::Reference(reference)) => Some(reference),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 enum ArgType {
     World,
+    CommandBuffer,
+    Executor,
+    Resources,
     Resource(TokenStream),
+    CopyResource(TokenStream),
+    OptionalResource(TokenStream),
 }
 
-fn arg(arg: &FnArg) -> &PatType {
+fn arg(arg: &FnArg) -> Result<&PatType> {
     match arg {
-        FnArg::Typed(ty) => ty,
-        _ => panic!("systems may not accept `self` parameters"),
+        FnArg::Typed(ty) => Ok(ty),
+        FnArg::Receiver(receiver) => Err(Error::new_spanned(
+            receiver,
+            "systems and event handlers may not take `self` parameters",
+        )),
     }
 }
@@ -0,0 +1,144 @@
+//! Systems whose work runs on a task pool across part of a tick.
+
+use crate::{CommandBuffer, OwnedResources, ResourcesEnum, World};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, JoinHandle, Thread};
+
+/// A boxed future which can be sent to the task pool.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A system which starts a future each tick rather than running to completion.
+///
+/// The future runs on the `Executor`'s task pool while other systems
+/// run, and is joined at the sync point given to `Executor::add_async`.
+/// It cannot borrow the resources or the world; instead, it returns a
+/// `CommandBuffer` which is applied to the world when it is joined.
+pub trait AsyncSystem: Send + Sync + 'static {
+    /// Starts the system's work for this tick.
+    fn start(&self, resources: &ResourcesEnum, world: &World) -> BoxFuture<CommandBuffer>;
+
+    /// Set up the system with the given resources and world.
+    fn set_up(&mut self, _resources: &mut OwnedResources, _world: &mut World) {}
+
+    /// Returns the name of this system.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of worker threads which run futures to completion.
+pub(crate) struct TaskPool {
+    sender: Mutex<Option<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl TaskPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("fecs-task-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver.lock().expect("task queue poisoned").recv();
+                        match job {
+                            Ok(job) => job(),
+                            // The pool was dropped.
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("failed to spawn task pool thread")
+            })
+            .collect();
+
+        Self {
+            sender: Mutex::new(Some(sender)),
+            workers,
+        }
+    }
+
+    /// Runs the given future on one of the worker threads.
+    pub fn spawn<T>(&self, future: impl Future<Output = T> + Send + 'static) -> Task<T>
+    where
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job = move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| block_on(future)));
+            let _ = sender.send(result);
+        };
+
+        self.sender
+            .lock()
+            .expect("task queue poisoned")
+            .as_ref()
+            .expect("task pool shut down")
+            .send(Box::new(job))
+            .expect("task pool threads exited");
+
+        Task { receiver }
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers once the queue is empty.
+        if let Ok(sender) = self.sender.get_mut() {
+            sender.take();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A future spawned on a `TaskPool`.
+pub(crate) struct Task<T> {
+    receiver: Receiver<thread::Result<T>>,
+}
+
+impl<T> Task<T> {
+    /// Blocks until the future completes, returning its output.
+    ///
+    /// # Panics
+    /// Resumes the panic if the future panicked.
+    pub fn join(self) -> T {
+        match self.receiver.recv().expect("task pool thread exited") {
+            Ok(output) => output,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// Wakes a thread blocked in `block_on`.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls the future on the current thread until it completes.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
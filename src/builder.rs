@@ -10,6 +10,15 @@ use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
 
+/// A group of components added to an entity together.
+///
+/// Usually derived with `#[derive(Bundle)]` on a struct whose
+/// fields are the components of the bundle.
+pub trait Bundle {
+    /// Adds each component of the bundle to `builder`.
+    fn add_to(self, builder: &mut EntityBuilder);
+}
+
 /// A builder that simplifies the creation of a single entity.
 #[derive(Default)]
 pub struct EntityBuilder {
@@ -83,6 +92,21 @@ impl EntityBuilder {
         self
     }
 
+    /// Adds each component of a bundle to an entity.
+    ///
+    /// Returns `Self` such that method calls for `EntityBuilder`can be chained.
+    pub fn with_bundle(mut self, bundle: impl Bundle) -> Self {
+        self.add_bundle(bundle);
+
+        self
+    }
+
+    /// Adds each component of a bundle to an entity.
+    pub fn add_bundle(&mut self, bundle: impl Bundle) -> &mut Self {
+        bundle.add_to(self);
+        self
+    }
+
     unsafe fn replace<C>(&mut self, component: C, offset: usize) {
         self.components
             .as_mut_ptr()
@@ -0,0 +1,84 @@
+use crate::{EntityBuilder, Event, World};
+use legion::entity::Entity;
+use legion::storage::Component;
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Records changes to a `World` to be applied later.
+///
+/// Systems can take a `&mut CommandBuffer` parameter. Its commands are
+/// submitted to the world when the system returns, and the `Executor`
+/// applies them after each system.
+#[derive(Default)]
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+}
+
+impl CommandBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an arbitrary change to the world.
+    pub fn exec(&mut self, f: impl FnOnce(&mut World) + Send + 'static) {
+        self.commands.push(Box::new(f));
+    }
+
+    /// Records spawning an entity with the components of `builder`.
+    pub fn spawn(&mut self, builder: EntityBuilder) {
+        self.exec(move |world| {
+            builder.build().spawn_in(world);
+        });
+    }
+
+    /// Records despawning the given entity.
+    pub fn despawn(&mut self, entity: Entity) {
+        self.exec(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    /// Records adding a component to the given entity.
+    /// Nothing happens if the entity has been despawned by then.
+    pub fn add(&mut self, entity: Entity, component: impl Component) {
+        self.exec(move |world| {
+            let _ = world.add(entity, component);
+        });
+    }
+
+    /// Records removing a component from the given entity.
+    /// Nothing happens if the entity has been despawned by then.
+    pub fn remove<C>(&mut self, entity: Entity)
+    where
+        C: Component,
+    {
+        self.exec(move |world| {
+            let _ = world.remove::<C>(entity);
+        });
+    }
+
+    /// Records queueing an event; see `World::queue_event`.
+    pub fn queue_event<E>(&mut self, event: E)
+    where
+        E: Event + Send,
+    {
+        self.exec(move |world| world.queue_event(event));
+    }
+
+    /// Returns the number of recorded commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns whether no commands have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Applies the recorded commands to the world, in the order they were recorded.
+    pub fn write(self, world: &mut World) {
+        for command in self.commands {
+            command(world);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Loading systems from dynamic libraries at runtime.
+
+use crate::{
+    Executor, OwnedResources, RawSystem, ResourcesEnum, Stage, SystemAccess, SystemError,
+    SystemOrdering, ThreadLocalResources, World,
+};
+use libloading::Library;
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Name of the registration function exported by a system library.
+const REGISTER_SYMBOL: &[u8] = b"fecs_register_systems\0";
+
+/// Signature of the registration function exported by a system library.
+type RegisterFn = unsafe extern "C" fn(registrar: &mut SystemRegistrar);
+
+/// Exports the registration function of a system library,
+/// to be loaded with `Executor::load_library`.
+///
+/// The library must be built as a `cdylib` with the same compiler
+/// and version of fecs as the executable loading it.
+///
+/// ```ignore
+/// fn register(registrar: &mut fecs::SystemRegistrar) {
+///     registrar.add(my_system);
+/// }
+///
+/// fecs::export_systems!(register);
+/// ```
+#[macro_export]
+macro_rules! export_systems {
+    ($register:path) => {
+        #[no_mangle]
+        #[allow(improper_ctypes_definitions)]
+        pub extern "C" fn fecs_register_systems(registrar: &mut $crate::SystemRegistrar) {
+            $register(registrar)
+        }
+    };
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LibraryError {
+    #[error("failed to load system library: {0}")]
+    Load(#[from] libloading::Error),
+    #[error("failed to copy system library: {0}")]
+    Copy(#[from] std::io::Error),
+    #[error("system library {0:?} is not loaded")]
+    NotLoaded(LibraryId),
+}
+
+/// Identifies a library loaded with `Executor::load_library`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LibraryId(u64);
+
+impl LibraryId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        LibraryId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Collects the systems registered by a system library.
+#[derive(Default)]
+pub struct SystemRegistrar {
+    systems: Vec<(Stage, Box<dyn RawSystem>)>,
+}
+
+impl SystemRegistrar {
+    /// Registers the given system to run in the stage given by `RawSystem::stage`.
+    pub fn add(&mut self, system: impl RawSystem) {
+        self.add_to_stage(system.stage(), system);
+    }
+
+    /// Registers the given system to run in the given stage.
+    pub fn add_to_stage(&mut self, stage: Stage, system: impl RawSystem) {
+        self.systems.push((stage, Box::new(system)));
+    }
+}
+
+/// A system library loaded into an `Executor`.
+///
+/// The library is loaded from a copy at a unique path, since loading
+/// the same path again, e.g. to reload it, would return the library
+/// which is already loaded rather than the new version.
+pub(crate) struct LoadedLibrary {
+    pub id: LibraryId,
+    pub path: PathBuf,
+    /// The copy of the file at `path` which was loaded.
+    copy: PathBuf,
+    /// Must outlive the systems registered by the library,
+    /// whose code and vtables it contains.
+    library: Option<Library>,
+}
+
+impl Drop for LoadedLibrary {
+    fn drop(&mut self) {
+        // Unloads the library before removing the file it was loaded from.
+        drop(self.library.take());
+        let _ = std::fs::remove_file(&self.copy);
+    }
+}
+
+impl LoadedLibrary {
+    /// Loads the library at `path` and calls its registration function,
+    /// returning the library along with the systems it registered.
+    ///
+    /// # Safety
+    /// See `Executor::load_library`.
+    pub unsafe fn load(
+        id: Option<LibraryId>,
+        path: &Path,
+    ) -> Result<(Self, Vec<(Stage, Box<dyn RawSystem>)>), LibraryError> {
+        let id = id.unwrap_or_else(LibraryId::next);
+        // Removes the copy if loading fails.
+        let mut loaded = LoadedLibrary {
+            id,
+            path: path.to_owned(),
+            copy: copy_path(id, path),
+            library: None,
+        };
+        std::fs::copy(path, &loaded.copy)?;
+
+        let library = loaded.library.insert(Library::new(&loaded.copy)?);
+        let mut registrar = SystemRegistrar::default();
+        {
+            let register = library.get::<RegisterFn>(REGISTER_SYMBOL)?;
+            register(&mut registrar);
+        }
+
+        let systems = registrar
+            .systems
+            .into_iter()
+            .map(|(stage, system)| (stage, LibrarySystem::wrap(system)))
+            .collect();
+        Ok((loaded, systems))
+    }
+}
+
+/// Returns a unique path in the temporary directory
+/// to copy the library at `path` to before loading it.
+fn copy_path(id: LibraryId, path: &Path) -> PathBuf {
+    static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "library".into(), |stem| stem.to_string_lossy());
+    let mut name = format!(
+        "{}-{}-{}-{}",
+        stem,
+        std::process::id(),
+        id.0,
+        GENERATION.fetch_add(1, Ordering::Relaxed)
+    );
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    std::env::temp_dir().join(name)
+}
+
+/// Returns a copy of `string` which lives until the program exits,
+/// reusing the copy made by an earlier call with the same string.
+fn intern(string: &str) -> &'static str {
+    static STRINGS: Lazy<Mutex<HashSet<&'static str>>> = Lazy::new(Default::default);
+
+    let mut strings = STRINGS.lock().expect("interned strings poisoned");
+    match strings.get(string) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(string.to_owned().into_boxed_str());
+            strings.insert(interned);
+            interned
+        }
+    }
+}
+
+/// A system registered by a library, whose name and labels are copied
+/// out of the library so that they remain valid once it is unloaded,
+/// e.g. in a `ScheduleError` or the name passed to a watchdog.
+struct LibrarySystem {
+    system: Box<dyn RawSystem>,
+    name: &'static str,
+    ordering: SystemOrdering,
+}
+
+impl LibrarySystem {
+    fn wrap(system: Box<dyn RawSystem>) -> Box<dyn RawSystem> {
+        let intern_all =
+            |labels: Vec<&str>| -> Vec<&'static str> { labels.into_iter().map(intern).collect() };
+        let ordering = system.ordering();
+        Box::new(LibrarySystem {
+            name: intern(system.name()),
+            ordering: SystemOrdering {
+                labels: intern_all(ordering.labels),
+                before: intern_all(ordering.before),
+                after: intern_all(ordering.after),
+            },
+            system,
+        })
+    }
+}
+
+impl RawSystem for LibrarySystem {
+    fn run(&self, resources: &ResourcesEnum, world: &mut World, executor: &Executor) {
+        self.system.run(resources, world, executor);
+    }
+
+    fn try_run(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.system.try_run(resources, world, executor)
+    }
+
+    fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
+        self.system.set_up(resources, world);
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn access(&self) -> Option<SystemAccess> {
+        self.system.access()
+    }
+
+    fn is_exclusive(&self) -> bool {
+        self.system.is_exclusive()
+    }
+
+    fn supports_shared(&self) -> bool {
+        self.system.supports_shared()
+    }
+
+    fn run_shared(&self, resources: &ResourcesEnum, world: &World, executor: &Executor) {
+        self.system.run_shared(resources, world, executor);
+    }
+
+    fn try_run_shared(
+        &self,
+        resources: &ResourcesEnum,
+        world: &World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.system.try_run_shared(resources, world, executor)
+    }
+
+    fn ordering(&self) -> SystemOrdering {
+        self.ordering.clone()
+    }
+
+    fn stage(&self) -> Stage {
+        self.system.stage()
+    }
+
+    fn is_thread_local(&self) -> bool {
+        self.system.is_thread_local()
+    }
+
+    fn run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) {
+        self.system
+            .run_thread_local(resources, thread_local, world, executor);
+    }
+
+    fn try_run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.system
+            .try_run_thread_local(resources, thread_local, world, executor)
+    }
+}
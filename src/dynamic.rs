@@ -0,0 +1,151 @@
+//! Queries over component types only known at runtime.
+
+use crate::World;
+use legion::entity::Entity;
+use legion::storage::ComponentTypeId;
+use smallvec::SmallVec;
+use std::ptr::NonNull;
+
+/// A query assembled at runtime from lists of component types,
+/// for use by scripting and inspection layers.
+///
+/// Matches every entity which has all of the read and written components.
+#[derive(Clone, Debug, Default)]
+pub struct DynamicQuery {
+    reads: Vec<ComponentTypeId>,
+    writes: Vec<ComponentTypeId>,
+}
+
+/// A type-erased pointer to a single component yielded by a `DynamicQuery`.
+#[derive(Copy, Clone, Debug)]
+pub struct DynamicComponent {
+    type_id: ComponentTypeId,
+    ptr: NonNull<u8>,
+    size: usize,
+    mutable: bool,
+}
+
+impl DynamicComponent {
+    /// Returns the type of the component.
+    pub fn type_id(&self) -> ComponentTypeId {
+        self.type_id
+    }
+
+    /// Returns a pointer to the component's data.
+    ///
+    /// The pointer is valid for the duration of the callback it was passed
+    /// to. It may only be written through if `is_mutable` returns `true`.
+    pub fn ptr(&self) -> NonNull<u8> {
+        self.ptr
+    }
+
+    /// Returns the size of the component in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether the component was requested for writing.
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+impl DynamicQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a component type to be read by this query.
+    pub fn read(mut self, type_id: ComponentTypeId) -> Self {
+        self.reads.push(type_id);
+        self
+    }
+
+    /// Adds a component type to be written by this query.
+    pub fn write(mut self, type_id: ComponentTypeId) -> Self {
+        self.writes.push(type_id);
+        self
+    }
+
+    /// Returns the component types read by this query.
+    pub fn reads(&self) -> &[ComponentTypeId] {
+        &self.reads
+    }
+
+    /// Returns the component types written by this query.
+    pub fn writes(&self) -> &[ComponentTypeId] {
+        &self.writes
+    }
+
+    /// Calls `f` with each matched entity and its components. Components
+    /// are passed in the order they were added, reads before writes.
+    ///
+    /// # Panics
+    /// Panics if a component type is both read and written.
+    pub fn for_each<F>(&self, world: &mut World, mut f: F)
+    where
+        F: FnMut(Entity, &[DynamicComponent]),
+    {
+        assert!(
+            self.reads.iter().all(|ty| !self.writes.contains(ty)),
+            "a dynamic query may not read and write the same component"
+        );
+
+        let storage = world.inner_mut().storage();
+        let mut components = SmallVec::<[DynamicComponent; 8]>::new();
+
+        for archetype in storage.archetypes() {
+            let layout = archetype.description().components();
+            let matches = self
+                .reads
+                .iter()
+                .chain(&self.writes)
+                .all(|ty| layout.iter().any(|(t, _)| t == ty));
+            if !matches {
+                continue;
+            }
+
+            for chunk in archetype.chunksets().iter().flat_map(|set| set.occupied()) {
+                // Hold the borrows of each component slice for the whole chunk.
+                let reads = self
+                    .reads
+                    .iter()
+                    .map(|ty| (*ty, chunk.components(*ty).unwrap().data_raw()))
+                    .collect::<SmallVec<[_; 8]>>();
+                let writes = self
+                    .writes
+                    .iter()
+                    .map(|ty| {
+                        // Safety: the world is borrowed mutably and
+                        // component types are disjoint across reads and writes.
+                        (*ty, unsafe {
+                            chunk.components(*ty).unwrap().data_raw_mut()
+                        })
+                    })
+                    .collect::<SmallVec<[_; 8]>>();
+
+                for (index, entity) in chunk.entities().iter().enumerate() {
+                    components.clear();
+                    let read_ptrs = reads
+                        .iter()
+                        .map(|(ty, (ptr, size, _))| (*ty, **ptr, *size, false));
+                    let write_ptrs = writes
+                        .iter()
+                        .map(|(ty, (ptr, size, _))| (*ty, **ptr, *size, true));
+                    for (type_id, base, size, mutable) in read_ptrs.chain(write_ptrs) {
+                        components.push(DynamicComponent {
+                            type_id,
+                            // Safety: `index` is within the chunk's length.
+                            ptr: NonNull::new(unsafe { base.add(index * size) })
+                                .expect("component pointer is null"),
+                            size,
+                            mutable,
+                        });
+                    }
+
+                    f(*entity, &components);
+                }
+            }
+        }
+    }
+}
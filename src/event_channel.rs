@@ -0,0 +1,144 @@
+//! Double-buffered event channels, read at the consumer's own pace.
+
+use std::marker::PhantomData;
+use std::mem;
+
+/// A double-buffered channel of events `E`, usually stored as a resource.
+///
+/// Unlike `EventHandlers`, which call handlers as soon as an event
+/// is triggered, events sent to an `Events` channel are kept until
+/// consumers read them with an `EventReader`.
+///
+/// `update` must be called once per tick, e.g. by a system in
+/// `Stage::First`. Events can be read during the tick they were
+/// sent in and the following one; after that they are dropped.
+pub struct Events<E> {
+    /// Events sent before the last `update`.
+    previous: Vec<E>,
+    /// Events sent since the last `update`.
+    current: Vec<E>,
+    /// Number of events sent before the first event in `previous`.
+    start: usize,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self {
+            previous: vec![],
+            current: vec![],
+            start: 0,
+        }
+    }
+}
+
+impl<E> Events<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends an event to the channel.
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    /// Returns an `EventWriter` sending to this channel.
+    pub fn writer(&mut self) -> EventWriter<E> {
+        EventWriter { events: self }
+    }
+
+    /// Returns an `EventReader` which only reads
+    /// events sent after this call.
+    pub fn reader(&self) -> EventReader<E> {
+        EventReader {
+            next: self.end(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Swaps the buffers, dropping the events sent before the previous `update`.
+    pub fn update(&mut self) {
+        self.start += self.previous.len();
+        self.previous.clear();
+        mem::swap(&mut self.previous, &mut self.current);
+    }
+
+    /// Removes all events from the channel, returning them in the order they were sent.
+    /// Readers will not see the drained events.
+    pub fn drain(&mut self) -> impl Iterator<Item = E> + '_ {
+        self.start = self.end();
+        self.previous.drain(..).chain(self.current.drain(..))
+    }
+
+    /// Returns the number of events in the channel.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Returns whether the channel contains no events.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of events sent before the next event.
+    fn end(&self) -> usize {
+        self.start + self.len()
+    }
+}
+
+/// Sends events to an `Events` channel.
+pub struct EventWriter<'a, E> {
+    events: &'a mut Events<E>,
+}
+
+impl<'a, E> EventWriter<'a, E> {
+    /// Sends an event to the channel.
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+
+    /// Sends each of the given events to the channel, in order.
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = E>) {
+        self.events.current.extend(events);
+    }
+}
+
+/// A cursor reading the events of an `Events` channel.
+///
+/// Each reader sees every event at most once. A default reader
+/// starts at the oldest event still in the channel; use
+/// `Events::reader` to only read events sent from now on.
+pub struct EventReader<E> {
+    /// Number of events sent before the next unread event.
+    next: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<E> EventReader<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the events sent since this reader last read
+    /// from `events`, in the order they were sent.
+    ///
+    /// Events dropped by `Events::update` or `Events::drain`
+    /// before they were read are skipped.
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> {
+        let skip = self.next.saturating_sub(events.start);
+        self.next = events.end();
+        events
+            .previous
+            .iter()
+            .chain(events.current.iter())
+            .skip(skip)
+    }
+}
@@ -1,11 +1,19 @@
 //! A basic event handling framework.
 
-use crate::{OwnedResources, ResourcesEnum, ResourcesProvider, World};
+use crate::util::{ConfiguredBuildHasher, HashMap};
+use crate::{OwnedResources, ResourcesEnum, ResourcesProvider, World, WorldOptions};
 use erasable::{erase, Erasable, ErasedPtr};
-use fxhash::FxHashMap;
 use smallvec::SmallVec;
-use std::any::TypeId;
+use std::any::{Any, TypeId};
 use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maximum number of rounds of queued events dispatched by
+/// `EventHandlers::dispatch_queued`, where each round dispatches
+/// the events queued by the handlers of the previous one.
+const MAX_DISPATCH_ROUNDS: usize = 256;
 
 /// Marker trait for types which can be used as events.
 pub trait Event: 'static {}
@@ -16,14 +24,64 @@ impl<T> Event for T where T: 'static {}
 #[doc(hidden)]
 pub trait RawEventHandler: Send + Sync + 'static {
     type Event: Event;
-    fn handle(&self, resources: &ResourcesEnum, world: &mut World, event: &Self::Event);
-    fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World);
+    /// The value returned by the handler; see `EventHandlers::trigger_fold`.
+    type Output: 'static;
+    fn handle(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        event: &Self::Event,
+    ) -> Self::Output;
+    /// Handles an event which the handler may modify. Handlers
+    /// taking `&mut Self::Event` override this, and handle
+    /// shared events through a clone.
+    fn handle_mut(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        event: &mut Self::Event,
+    ) -> Self::Output {
+        self.handle(resources, world, event)
+    }
+    /// Handles a batch of events, discarding their outputs. Handlers
+    /// taking a slice override this to handle the whole batch at once.
+    fn handle_batch(&self, resources: &ResourcesEnum, world: &mut World, events: &[Self::Event]) {
+        for event in events {
+            self.handle(resources, world, event);
+        }
+    }
+    /// Sets up the handler, e.g. inserting the resources it needs.
+    fn set_up(&mut self, _resources: &mut OwnedResources, _world: &mut World) {}
+    /// Returns the priority of the handler. Handlers with a higher
+    /// priority are called before those with a lower one.
+    fn priority(&self) -> i32 {
+        0
+    }
 }
 
 trait TypeErasedEventHandler: Send + Sync + 'static {
-    unsafe fn handle(&self, resources: &ResourcesEnum, world: &mut World, event: ErasedPtr);
+    /// Handles the event, returning whether the handler was called.
+    unsafe fn handle(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        event: ErasedPtr,
+        output: Option<ErasedPtr>,
+    ) -> bool;
+    unsafe fn handle_batch(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        events: ErasedPtr,
+        len: usize,
+    );
     fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World);
 
+    /// Returns the type ID of the handler's output.
+    fn output_type(&self) -> TypeId;
+
+    fn priority(&self) -> i32;
+
     fn name(&self) -> &'static str {
         std::any::type_name::<Self>()
     }
@@ -35,36 +93,498 @@ where
     E: Event,
 {
     /// Safety: the type of `event` must be the same
-    /// as the event type handled by this handler.
-    unsafe fn handle(&self, resources: &ResourcesEnum, world: &mut World, event: ErasedPtr) {
-        <Self as RawEventHandler>::handle(self, resources, world, E::unerase(event).as_ref())
+    /// as the event type handled by this handler, `event` must
+    /// be valid for writes, and `output`, if given, must point
+    /// to an `Option<H::Output>`.
+    unsafe fn handle(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        event: ErasedPtr,
+        output: Option<ErasedPtr>,
+    ) -> bool {
+        let result = <Self as RawEventHandler>::handle_mut(
+            self,
+            resources,
+            world,
+            E::unerase(event).as_mut(),
+        );
+        if let Some(output) = output {
+            *<Option<H::Output>>::unerase(output).as_mut() = Some(result);
+        }
+        true
+    }
+
+    /// Safety: `events` must point to the first of `len` events
+    /// of the event type handled by this handler.
+    unsafe fn handle_batch(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        events: ErasedPtr,
+        len: usize,
+    ) {
+        let events = std::slice::from_raw_parts(E::unerase(events).as_ptr() as *const E, len);
+        <Self as RawEventHandler>::handle_batch(self, resources, world, events)
     }
 
     fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
         <Self as RawEventHandler>::set_up(self, resources, world);
     }
+
+    fn output_type(&self) -> TypeId {
+        TypeId::of::<H::Output>()
+    }
+
+    fn priority(&self) -> i32 {
+        <Self as RawEventHandler>::priority(self)
+    }
+}
+
+/// A handler which is only called for events matching a filter.
+struct Filtered<H, F> {
+    handler: H,
+    filter: F,
+}
+
+impl<H, E, F> TypeErasedEventHandler for Filtered<H, F>
+where
+    H: RawEventHandler<Event = E>,
+    E: Event + Clone,
+    F: Fn(&E) -> bool + Send + Sync + 'static,
+{
+    /// Safety: see the implementation for `RawEventHandler`s.
+    unsafe fn handle(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        event: ErasedPtr,
+        output: Option<ErasedPtr>,
+    ) -> bool {
+        (self.filter)(E::unerase(event).as_ref())
+            && TypeErasedEventHandler::handle(&self.handler, resources, world, event, output)
+    }
+
+    /// Safety: see the implementation for `RawEventHandler`s.
+    unsafe fn handle_batch(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        events: ErasedPtr,
+        len: usize,
+    ) {
+        let events = std::slice::from_raw_parts(E::unerase(events).as_ptr() as *const E, len);
+        // The accepted events are cloned into a batch of their own,
+        // unless all of them were accepted.
+        if events.iter().all(|event| (self.filter)(event)) {
+            <H as RawEventHandler>::handle_batch(&self.handler, resources, world, events);
+        } else {
+            let accepted: Vec<E> = events
+                .iter()
+                .filter(|event| (self.filter)(event))
+                .cloned()
+                .collect();
+            if !accepted.is_empty() {
+                <H as RawEventHandler>::handle_batch(&self.handler, resources, world, &accepted);
+            }
+        }
+    }
+
+    fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
+        <H as RawEventHandler>::set_up(&mut self.handler, resources, world);
+    }
+
+    fn output_type(&self) -> TypeId {
+        TypeId::of::<H::Output>()
+    }
+
+    fn priority(&self) -> i32 {
+        <H as RawEventHandler>::priority(&self.handler)
+    }
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<H>()
+    }
+}
+
+/// A registered event handler.
+struct HandlerEntry {
+    id: HandlerId,
+    /// The group set with `EventHandlers::add_to_group`.
+    group: Option<String>,
+    enabled: AtomicBool,
+    /// Whether the handler was added with `EventHandlers::add_once`.
+    once: bool,
+    /// Whether a one-shot handler has already been called.
+    spent: AtomicBool,
+    handler: Box<dyn TypeErasedEventHandler>,
+}
+
+impl HandlerEntry {
+    /// Returns whether the handler should be called,
+    /// marking one-shot handlers as spent.
+    ///
+    /// Handlers are claimed right before they are called, so a one-shot
+    /// handler is kept if an earlier handler panics, but is not called
+    /// again by events its own call triggers.
+    fn claim(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+            && !(self.once && self.spent.swap(true, Ordering::AcqRel))
+    }
+}
+
+type HandlerVec = SmallVec<[HandlerEntry; 4]>;
+
+/// Identifies a handler added to `EventHandlers`, for use with `EventHandlers::remove`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HandlerId {
+    event: TypeId,
+    id: u64,
+}
+
+type DispatchFn = fn(&EventHandlers, &ResourcesEnum, &mut World, Box<dyn Any + Send>);
+
+/// An event queued with `World::queue_event`, waiting to be dispatched.
+pub(crate) struct QueuedEvent {
+    event: Box<dyn Any + Send>,
+    dispatch: DispatchFn,
+}
+
+impl QueuedEvent {
+    pub fn new<E>(event: E) -> Self
+    where
+        E: Event + Send,
+    {
+        Self {
+            event: Box::new(event),
+            dispatch: dispatch::<E>,
+        }
+    }
+}
+
+fn dispatch<E>(
+    handlers: &EventHandlers,
+    resources: &ResourcesEnum,
+    world: &mut World,
+    event: Box<dyn Any + Send>,
+) where
+    E: Event,
+{
+    let event = *event
+        .downcast::<E>()
+        .expect("queued event has the wrong type");
+    handlers.trigger(resources, world, event);
 }
 
-type HandlerVec = SmallVec<[Box<dyn TypeErasedEventHandler>; 4]>;
+/// Dispatch statistics for one event type, recorded
+/// when profiling is enabled with `EventHandlers::set_profiling`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventMetrics {
+    /// Name of the event type.
+    pub event: &'static str,
+    /// Number of times the event was triggered.
+    pub triggers: u64,
+    /// Number of events dispatched, counting each event of a batch.
+    pub events: u64,
+    /// Total time spent dispatching the event to its handlers,
+    /// including events triggered by the handlers themselves.
+    pub handler_time: Duration,
+}
+
+/// Describes the dispatch of an event, returned by `EventHandlers::trigger`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TriggerInfo {
+    /// Number of handlers which were called.
+    pub handlers: usize,
+}
+
+impl TriggerInfo {
+    /// Returns whether any handler was called.
+    pub fn handled(&self) -> bool {
+        self.handlers > 0
+    }
+}
 
 /// Stores event handlers and allows triggering events.
+///
+/// With the `tracing` feature, each trigger and each handler
+/// call is wrapped in a trace-level span recording the
+/// event type and handler name.
 #[derive(Default)]
-pub struct EventHandlers(FxHashMap<TypeId, HandlerVec>);
+pub struct EventHandlers {
+    handlers: HashMap<TypeId, HandlerVec>,
+    next_id: u64,
+    /// Whether to record `EventMetrics`.
+    profiling: AtomicBool,
+    metrics: Mutex<HashMap<TypeId, EventMetrics>>,
+    /// Handlers inherited by a child created with `child_of`.
+    parent: Option<Arc<EventHandlers>>,
+    /// Event types whose parent handlers are hidden from this child.
+    shadowed: Vec<TypeId>,
+    /// The latest value of each event triggered with `trigger_sticky`.
+    sticky: Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+}
 
 impl EventHandlers {
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Registers an event handler.
-    pub fn add<E>(&mut self, handler: impl RawEventHandler<Event = E>)
+    /// Creates an empty set of handlers using the hasher selected in `options`.
+    pub fn with_options(options: &WorldOptions) -> Self {
+        Self {
+            handlers: HashMap::with_hasher(ConfiguredBuildHasher::new(options.hasher)),
+            next_id: 0,
+            profiling: AtomicBool::new(false),
+            metrics: Mutex::new(HashMap::with_hasher(ConfiguredBuildHasher::new(
+                options.hasher,
+            ))),
+            parent: None,
+            shadowed: vec![],
+            sticky: Mutex::new(HashMap::with_hasher(ConfiguredBuildHasher::new(
+                options.hasher,
+            ))),
+        }
+    }
+
+    /// Creates an empty child of `parent`, e.g. for a per-world event scope.
+    ///
+    /// Triggering an event on the child calls the parent's handlers,
+    /// then the child's own handlers, regardless of their priorities,
+    /// unless the event type is shadowed with `shadow`. Changes to the
+    /// child never affect the parent, and `set_up` only sets up the
+    /// child's own handlers.
+    pub fn child_of(parent: Arc<EventHandlers>) -> Self {
+        Self {
+            parent: Some(parent),
+            ..Self::default()
+        }
+    }
+
+    /// Hides the parent's handlers for the event `E`,
+    /// so that only this child's handlers are called.
+    pub fn shadow<E>(&mut self)
+    where
+        E: Event,
+    {
+        let type_id = TypeId::of::<E>();
+        if !self.shadowed.contains(&type_id) {
+            self.shadowed.push(type_id);
+        }
+    }
+
+    /// Returns the parent of a child created with `child_of`.
+    pub fn parent(&self) -> Option<&Arc<EventHandlers>> {
+        self.parent.as_ref()
+    }
+
+    /// Returns the handlers of the event type, starting with
+    /// the handlers inherited from the parent, if any.
+    /// Each must be claimed before it is called.
+    fn handlers_of(&self, event: TypeId) -> SmallVec<[&HandlerEntry; 8]> {
+        let mut handlers = match &self.parent {
+            Some(parent) if !self.shadowed.contains(&event) => parent.handlers_of(event),
+            _ => SmallVec::new(),
+        };
+        if let Some(own) = self.handlers.get(&event) {
+            handlers.extend(
+                own.iter()
+                    .filter(|entry| !entry.spent.load(Ordering::Acquire)),
+            );
+        }
+        handlers
+    }
+
+    /// Drops the one-shot handlers which have been called,
+    /// which triggering can't do with only shared access.
+    fn purge_spent(&mut self) {
+        for handlers in self.handlers.values_mut() {
+            handlers.retain(|entry| !*entry.spent.get_mut());
+        }
+    }
+
+    /// Enables or disables recording `EventMetrics` for each event type.
+    pub fn set_profiling(&self, enabled: bool) {
+        self.profiling.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns the metrics recorded for each triggered event
+    /// type, sorted by descending handler time.
+    pub fn metrics(&self) -> Vec<EventMetrics> {
+        let mut metrics: Vec<_> = self
+            .metrics
+            .lock()
+            .expect("event metrics poisoned")
+            .values()
+            .copied()
+            .collect();
+        metrics.sort_by(|a, b| b.handler_time.cmp(&a.handler_time));
+        metrics
+    }
+
+    /// Clears the recorded metrics.
+    pub fn reset_metrics(&self) {
+        self.metrics.lock().expect("event metrics poisoned").clear();
+    }
+
+    /// Returns the start time of a dispatch if profiling is enabled.
+    fn start_profiling(&self) -> Option<Instant> {
+        if self.profiling.load(Ordering::Relaxed) {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    /// Records a dispatch of `events` events `E` started at `start`.
+    fn record<E>(&self, start: Option<Instant>, events: usize)
     where
         E: Event,
     {
-        self.0
+        let start = match start {
+            Some(start) => start,
+            None => return,
+        };
+        let elapsed = start.elapsed();
+
+        let mut metrics = self.metrics.lock().expect("event metrics poisoned");
+        let metrics = metrics
             .entry(TypeId::of::<E>())
-            .or_default()
-            .push(Box::new(handler))
+            .or_insert_with(|| EventMetrics {
+                event: std::any::type_name::<E>(),
+                ..EventMetrics::default()
+            });
+        metrics.triggers += 1;
+        metrics.events += events as u64;
+        metrics.handler_time += elapsed;
+    }
+
+    /// Registers an event handler, returning an ID with which it can be removed.
+    ///
+    /// Handlers are called in order of descending `RawEventHandler::priority`,
+    /// and in the order they were added within a priority. Handlers
+    /// inherited from a parent are called before the child's own,
+    /// regardless of priority.
+    pub fn add<E>(&mut self, handler: impl RawEventHandler<Event = E>) -> HandlerId
+    where
+        E: Event,
+    {
+        self.add_erased(TypeId::of::<E>(), None, false, Box::new(handler))
+    }
+
+    /// Registers an event handler, immediately calling it with the
+    /// latest event `E` triggered with `trigger_sticky`, if any.
+    ///
+    /// This lets handlers registered late, e.g. by plugins,
+    /// catch up on events such as the world being loaded.
+    /// The handler is not set up before it is called.
+    pub fn add_and_replay<E>(
+        &mut self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        handler: impl RawEventHandler<Event = E>,
+    ) -> HandlerId
+    where
+        E: Event + Clone,
+    {
+        let latest = self
+            .sticky
+            .get_mut()
+            .expect("sticky events poisoned")
+            .get(&TypeId::of::<E>())
+            .and_then(|event| event.downcast_ref::<E>())
+            .cloned();
+        if let Some(mut event) = latest {
+            handler.handle_mut(&resources.as_resources_ref(), world, &mut event);
+        }
+        self.add(handler)
+    }
+
+    /// Registers an event handler which is removed after it is first called.
+    ///
+    /// The handler is claimed right before it is called, and dropped
+    /// by the next call to `add`, `remove`, `clear` or `set_up`.
+    pub fn add_once<E>(&mut self, handler: impl RawEventHandler<Event = E>) -> HandlerId
+    where
+        E: Event,
+    {
+        self.add_erased(TypeId::of::<E>(), None, true, Box::new(handler))
+    }
+
+    /// Registers an event handler as a member of the given group,
+    /// which can be disabled with `set_group_enabled`.
+    pub fn add_to_group<E>(
+        &mut self,
+        group: &str,
+        handler: impl RawEventHandler<Event = E>,
+    ) -> HandlerId
+    where
+        E: Event,
+    {
+        self.add_erased(
+            TypeId::of::<E>(),
+            Some(group.to_owned()),
+            false,
+            Box::new(handler),
+        )
+    }
+
+    /// Registers an event handler which is only called for events
+    /// for which `filter` returns `true`.
+    ///
+    /// The filter runs before the handler borrows its resources.
+    /// With `trigger_batched`, the handler is called once with
+    /// a batch of clones of the accepted events.
+    pub fn add_filtered<E>(
+        &mut self,
+        handler: impl RawEventHandler<Event = E>,
+        filter: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> HandlerId
+    where
+        E: Event + Clone,
+    {
+        self.add_erased(
+            TypeId::of::<E>(),
+            None,
+            false,
+            Box::new(Filtered { handler, filter }),
+        )
+    }
+
+    fn add_erased(
+        &mut self,
+        event: TypeId,
+        group: Option<String>,
+        once: bool,
+        handler: Box<dyn TypeErasedEventHandler>,
+    ) -> HandlerId {
+        let id = HandlerId {
+            event,
+            id: self.next_id,
+        };
+        self.next_id += 1;
+
+        self.purge_spent();
+        let handlers = self.handlers.entry(event).or_default();
+        // Keep handlers sorted by descending priority,
+        // and in insertion order within a priority.
+        let priority = handler.priority();
+        let index = handlers
+            .iter()
+            .position(|entry| entry.handler.priority() < priority)
+            .unwrap_or(handlers.len());
+        handlers.insert(
+            index,
+            HandlerEntry {
+                id,
+                group,
+                enabled: AtomicBool::new(true),
+                once,
+                spent: AtomicBool::new(false),
+                handler,
+            },
+        );
+        id
     }
 
     /// Builder function to add an event handler.
@@ -76,32 +596,282 @@ impl EventHandlers {
         self
     }
 
+    /// Removes the handler with the given ID.
+    /// Returns whether the handler was registered
+    /// and, if it is a one-shot handler, not yet called.
+    pub fn remove(&mut self, id: HandlerId) -> bool {
+        let handlers = match self.handlers.get_mut(&id.event) {
+            Some(handlers) => handlers,
+            None => return false,
+        };
+        let removed = match handlers.iter().position(|entry| entry.id == id) {
+            Some(index) => {
+                let mut entry = handlers.remove(index);
+                !*entry.spent.get_mut()
+            }
+            None => false,
+        };
+        self.purge_spent();
+        removed
+    }
+
+    /// Removes all handlers for the event `E`.
+    pub fn clear<E>(&mut self)
+    where
+        E: Event,
+    {
+        self.handlers.remove(&TypeId::of::<E>());
+        self.purge_spent();
+    }
+
+    /// Enables or disables all handlers in the given group.
+    /// Disabled handlers are skipped when triggering events.
+    ///
+    /// Returns whether any handler is in the given group.
+    pub fn set_group_enabled(&self, group: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for entry in self
+            .handlers
+            .values()
+            .flatten()
+            .filter(|entry| entry.group.as_deref() == Some(group))
+        {
+            entry.enabled.store(enabled, Ordering::Relaxed);
+            found = true;
+        }
+        found
+    }
+
+    /// Sets up each handler. One-shot handlers which
+    /// have been called are dropped.
     pub fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
-        for handler in self.0.values_mut().flatten() {
-            handler.set_up(resources, world);
+        self.purge_spent();
+        for entry in self.handlers.values_mut().flatten() {
+            entry.handler.set_up(resources, world);
         }
     }
 
     /// Emits the given event `E` with the given resources and world.
-    pub fn trigger<E>(&self, resources: &impl ResourcesProvider, world: &mut World, event: E)
+    ///
+    /// The returned `TriggerInfo` tells whether any handler was called.
+    pub fn trigger<E>(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        event: E,
+    ) -> TriggerInfo
+    where
+        E: Event,
+    {
+        let mut event = event;
+        self.trigger_mut(resources, world, &mut event)
+    }
+
+    /// Emits the given event `E`, then retains it as the latest
+    /// value of `E`, which is replayed to handlers registered
+    /// with `add_and_replay`.
+    pub fn trigger_sticky<E>(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        event: E,
+    ) -> TriggerInfo
+    where
+        E: Event + Send + Sync,
+    {
+        let mut event = event;
+        let info = self.trigger_mut(resources, world, &mut event);
+        self.sticky
+            .lock()
+            .expect("sticky events poisoned")
+            .insert(TypeId::of::<E>(), Box::new(event));
+        info
+    }
+
+    /// Forgets the latest value of the sticky event `E`.
+    pub fn clear_sticky<E>(&self)
+    where
+        E: Event,
+    {
+        self.sticky
+            .lock()
+            .expect("sticky events poisoned")
+            .remove(&TypeId::of::<E>());
+    }
+
+    /// Emits the given event `E`, allowing handlers to modify it.
+    ///
+    /// Handlers run in descending priority, then insertion order.
+    /// Handlers inherited from a parent created with `child_of` run
+    /// before the child's own, regardless of priority. Changes made by
+    /// handlers taking `&mut E` are seen by later handlers and,
+    /// once this returns, by the caller.
+    pub fn trigger_mut<E>(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        event: &mut E,
+    ) -> TriggerInfo
+    where
+        E: Event,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("trigger", event = std::any::type_name::<E>()).entered();
+        let start = self.start_profiling();
+        let resources = resources.as_resources_ref();
+        let mut info = TriggerInfo::default();
+        for entry in self.handlers_of(TypeId::of::<E>()) {
+            if !entry.claim() {
+                continue;
+            }
+            let handler = &entry.handler;
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("event_handler", handler = handler.name()).entered();
+            // Safety: we know that the type of `event` is the same type
+            // handled by this handler since it's in the handlers vec
+            // for that event type ID. The pointer comes from
+            // a unique borrow, so handlers may write through it.
+            let called = unsafe {
+                handler.handle(&resources, world, erase(NonNull::from(&mut *event)), None)
+            };
+            if called {
+                info.handlers += 1;
+            }
+        }
+        self.record::<E>(start, 1);
+        info
+    }
+
+    /// Emits the given event `E`, folding the values returned by
+    /// its handlers into a result starting at `init`.
+    ///
+    /// Handlers returning a type other than `R` are called as
+    /// usual, but their results do not contribute.
+    pub fn trigger_fold<E, R>(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        event: E,
+        init: R,
+        mut fold: impl FnMut(R, R) -> R,
+    ) -> R
     where
         E: Event,
+        R: 'static,
     {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("trigger", event = std::any::type_name::<E>()).entered();
+        let start = self.start_profiling();
         let mut event = event;
-        if let Some(handlers) = self.0.get(&TypeId::of::<E>()) {
-            for handler in handlers {
-                // Safety: we know that the type of `event` is the same type
-                // handled by this handler since it's in the handlers vec
-                // for that event type ID.
-                unsafe {
-                    handler.handle(
-                        &resources.as_resources_ref(),
-                        world,
-                        erase(NonNull::new_unchecked((&mut event) as *mut E)),
-                    );
-                }
+        let mut result = init;
+        let resources = resources.as_resources_ref();
+        for entry in self.handlers_of(TypeId::of::<E>()) {
+            if !entry.claim() {
+                continue;
+            }
+            let handler = &entry.handler;
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("event_handler", handler = handler.name()).entered();
+            let mut output: Option<R> = None;
+            let output_ptr = if handler.output_type() == TypeId::of::<R>() {
+                Some(erase(NonNull::from(&mut output)))
+            } else {
+                None
+            };
+
+            // Safety: the event type is checked as in `trigger`,
+            // and `output` is only passed if the handler's
+            // output type is `R`.
+            unsafe {
+                handler.handle(
+                    &resources,
+                    world,
+                    erase(NonNull::new_unchecked((&mut event) as *mut E)),
+                    output_ptr,
+                );
+            }
+
+            if let Some(output) = output {
+                result = fold(result, output);
+            }
+        }
+        self.record::<E>(start, 1);
+        result
+    }
+
+    /// Emits each of the given events `E` with the given resources and world.
+    ///
+    /// Handlers taking a slice of events are called once with the
+    /// whole batch; other handlers are called once per event. Each
+    /// handler handles the entire batch before the next handler runs.
+    ///
+    /// Handlers taking `&mut E` are called with a clone of each
+    /// event, so their changes are not seen by other handlers.
+    pub fn trigger_batched<E>(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        events: &[E],
+    ) where
+        E: Event,
+    {
+        if events.is_empty() {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("trigger", event = std::any::type_name::<E>()).entered();
+        let start = self.start_profiling();
+        let resources = resources.as_resources_ref();
+        for entry in self.handlers_of(TypeId::of::<E>()) {
+            if !entry.claim() {
+                continue;
+            }
+            let handler = &entry.handler;
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("event_handler", handler = handler.name()).entered();
+            // Safety: the type of `events` is the type handled by
+            // this handler, as in `trigger`, and the pointer
+            // covers all `events.len()` events.
+            unsafe {
+                handler.handle_batch(
+                    &resources,
+                    world,
+                    erase(NonNull::from(events).cast::<E>()),
+                    events.len(),
+                );
+            }
+        }
+        self.record::<E>(start, events.len());
+    }
+
+    /// Dispatches the events queued with `World::queue_event`
+    /// to their handlers, in the order they were queued.
+    ///
+    /// Events queued by the handlers are dispatched as well, up to 256
+    /// rounds deep; events queued after that are left for the next call,
+    /// so that handlers re-queueing their own event can't loop forever.
+    /// Returns the number of events dispatched.
+    pub fn dispatch_queued(&self, resources: &impl ResourcesProvider, world: &mut World) -> usize {
+        let resources = resources.as_resources_ref();
+        let mut dispatched = 0;
+        for _ in 0..MAX_DISPATCH_ROUNDS {
+            let events = world.take_queued_events();
+            if events.is_empty() {
+                return dispatched;
+            }
+
+            dispatched += events.len();
+            for QueuedEvent { event, dispatch } in events {
+                dispatch(self, &resources, world, event);
             }
         }
+        log::warn!(
+            "events were still being queued after {} rounds of dispatch; \
+             deferring the rest to the next dispatch",
+            MAX_DISPATCH_ROUNDS
+        );
+        dispatched
     }
 }
 
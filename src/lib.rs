@@ -1,22 +1,55 @@
+mod async_system;
 mod builder;
+mod commands;
+#[cfg(feature = "dylib")]
+mod dylib;
+mod dynamic;
 mod entity_ref;
+mod event_channel;
 mod events;
 mod query;
+#[cfg(feature = "serialize")]
+mod resource_registry;
 mod resources;
+mod schedule_config;
 mod system;
+mod time;
+mod util;
 mod world;
 
-pub use builder::{BuiltEntity, EntityBuilder};
-pub use fecs_macros::{event_handler, system};
-pub use legion::entity::Entity;
-// pub use query::{Query, QueryBorrow, QueryElement};
+pub use async_system::{AsyncSystem, BoxFuture};
+pub use builder::{BuiltEntity, Bundle, EntityBuilder};
+pub use commands::CommandBuffer;
+#[cfg(feature = "dylib")]
+pub use dylib::{LibraryError, LibraryId, SystemRegistrar};
+pub use dynamic::{DynamicComponent, DynamicQuery};
 pub use entity_ref::EntityRef;
-pub use events::{Event, EventHandlers, RawEventHandler};
-pub use resources::{OwnedResources, Ref, RefMut, RefResources, ResourcesEnum, ResourcesProvider};
-pub use system::{Executor, RawSystem};
-pub use world::World;
+pub use event_channel::{EventReader, EventWriter, Events};
+pub use events::{Event, EventHandlers, EventMetrics, HandlerId, RawEventHandler, TriggerInfo};
+pub use fecs_macros::{event_handler, system, Bundle};
+pub use legion::entity::Entity;
+pub use query::{
+    with, without, Access, ClonedQuery, ClonedQueryElement, Query, QueryBorrow, QueryCombinations,
+    QueryElement, QuerySingleError, With, Without,
+};
+#[cfg(feature = "serialize")]
+pub use resource_registry::{ResourceRegistry, SerializeResources};
+pub use resources::{
+    FrozenResources, OwnedResources, Ref, RefMut, RefResources, ResourceBundle, ResourceError,
+    ResourceFetch, ResourceId, ResourcesEnum, ResourcesProvider, ThreadLocalResources,
+};
+pub use schedule_config::{ScheduleConfig, SystemConfig};
+pub use system::{
+    log_system_error, ErrorPolicy, Executor, ExecutorBuilder, RawSystem, ScheduleError, Stage,
+    SubExecutor, SystemAccess, SystemError, SystemGroup, SystemInfo, SystemOrdering, SystemTimings,
+    Watchdog, WorldAccess,
+};
+pub use time::{FixedTimestep, Tick, Time};
+pub use util::HasherKind;
+pub use world::{World, WorldOptions};
 
 pub use legion::filter::filter_fns::*;
 pub use legion::query::{IntoQuery, Read, TryRead, TryWrite, Write};
+pub use legion::storage::ComponentTypeId;
 
 pub use legion;
@@ -1,23 +1,97 @@
 use crate::World;
+use legion::filter::filter_fns::component;
+use legion::filter::{ComponentFilter, EntityFilter, EntityFilterTuple, Passthrough};
 use legion::prelude::{Entity, Read, Write};
 use legion::query::View;
-use legion::query::{IntoQuery, ViewElement};
-use legion::storage::Component;
+use legion::query::{Chunk, DefaultFilter, IntoQuery, ReadOnly, ViewElement};
+use legion::storage::{Component, ComponentTypeId};
+use std::ops::{BitAnd, ControlFlow, Not};
+
+/// Error returned by `QueryBorrow::try_single` and `QueryBorrow::try_single_mut`.
+#[derive(Debug, thiserror::Error)]
+pub enum QuerySingleError {
+    #[error("no entities matched the query")]
+    NoEntities,
+    #[error("more than one entity matched the query")]
+    MultipleEntities,
+}
+
+/// Filter matching entities which have the component `C`.
+pub type With<C> = EntityFilterTuple<ComponentFilter<C>, Passthrough, Passthrough>;
+
+/// Filter matching entities which do not have the component `C`.
+pub type Without<C> = <With<C> as Not>::Output;
+
+/// Creates a filter matching entities which have the component `C`.
+pub fn with<C>() -> With<C>
+where
+    C: Component,
+{
+    component::<C>()
+}
+
+/// Creates a filter matching entities which do not have the component `C`.
+pub fn without<C>() -> Without<C>
+where
+    C: Component,
+{
+    !component::<C>()
+}
 
 /// A query that references a given world.
-pub struct QueryBorrow<'a, Q>
+///
+/// `F` is the legion filter applied to the query; additional
+/// filters are added with `QueryBorrow::filter`.
+pub struct QueryBorrow<'a, Q, F = <<Q as Query>::Legion as DefaultFilter>::Filter>
 where
     Q: Query,
+    F: EntityFilter,
 {
     pub(crate) world: &'a mut World,
-    pub(crate) inner:
-        legion::query::Query<Q::Legion, <Q::Legion as legion::query::DefaultFilter>::Filter>,
+    pub(crate) inner: legion::query::Query<Q::Legion, F>,
 }
 
-impl<'a, Q> QueryBorrow<'a, Q>
+impl<'a, Q, F> QueryBorrow<'a, Q, F>
 where
     Q: Query,
+    F: EntityFilter,
 {
+    /// Adds an additional filter to this query, e.g. `tag_value(&Biome::Desert)`.
+    ///
+    /// Archetype and chunk filters are evaluated per chunk, so
+    /// non-matching chunks are skipped entirely.
+    pub fn filter<T>(self, filter: T) -> QueryBorrow<'a, Q, <F as BitAnd<T>>::Output>
+    where
+        T: EntityFilter,
+        F: BitAnd<T>,
+        <F as BitAnd<T>>::Output: EntityFilter,
+    {
+        QueryBorrow {
+            world: self.world,
+            inner: self.inner.filter(filter),
+        }
+    }
+
+    /// Restricts this query to entities which have the component `C`.
+    pub fn with<C>(self) -> QueryBorrow<'a, Q, <F as BitAnd<With<C>>>::Output>
+    where
+        C: Component,
+        F: BitAnd<With<C>>,
+        <F as BitAnd<With<C>>>::Output: EntityFilter,
+    {
+        self.filter(with::<C>())
+    }
+
+    /// Restricts this query to entities which do not have the component `C`.
+    pub fn without<C>(self) -> QueryBorrow<'a, Q, <F as BitAnd<Without<C>>>::Output>
+    where
+        C: Component,
+        F: BitAnd<Without<C>>,
+        <F as BitAnd<Without<C>>>::Output: EntityFilter,
+    {
+        self.filter(without::<C>())
+    }
+
     pub fn iter_mut(
         &mut self,
     ) -> impl Iterator<Item = <<Q::Legion as View>::Iter as Iterator>::Item> {
@@ -29,10 +103,280 @@ where
     ) -> impl Iterator<Item = (Entity, <<Q::Legion as View>::Iter as Iterator>::Item)> {
         self.inner.iter_entities_mut(self.world.inner_mut())
     }
+
+    /// Clears `buffer` and fills it with the entities matched by this query
+    /// along with clones of their components.
+    ///
+    /// Reusing the same buffer across ticks avoids allocating each time.
+    pub fn collect_into(&mut self, buffer: &mut Vec<(Entity, Q::Owned)>)
+    where
+        Q: ClonedQuery,
+    {
+        buffer.clear();
+        buffer.extend(
+            self.iter_entities_mut()
+                .map(|(entity, item)| (entity, Q::clone_item(&item))),
+        );
+    }
+
+    /// Returns the number of entities matched by this query.
+    ///
+    /// This only sums the lengths of matching chunks; no component data is accessed.
+    pub fn count(&mut self) -> usize {
+        self.inner
+            .iter_chunks_mut(self.world.inner_mut())
+            .map(|chunk| chunk.entities().len())
+            .sum()
+    }
+
+    /// Returns an iterator over the chunks matched by this query.
+    ///
+    /// This is the cheapest way to visit every chunk in turn.
+    pub fn iter_chunks_mut(&mut self) -> impl Iterator<Item = Chunk<'_, Q::Legion>> {
+        self.inner.iter_chunks_mut(self.world.inner_mut())
+    }
+
+    /// Returns the number of chunks matched by this query.
+    ///
+    /// Together with `get_chunk_unchecked`, this allows splitting query
+    /// work across tasks by chunk index.
+    pub fn chunk_count(&mut self) -> usize {
+        self.inner.iter_chunks_mut(self.world.inner_mut()).count()
+    }
+
+    /// Returns the `index`th chunk matched by this query, or `None`
+    /// if `index >= self.chunk_count()`.
+    ///
+    /// Matched chunks are not cached, so this walks the first `index`
+    /// chunks on each call; fetching every chunk by index is quadratic
+    /// in the number of chunks. Use `iter_chunks_mut` to visit them all.
+    pub fn get_chunk(&mut self, index: usize) -> Option<Chunk<'_, Q::Legion>> {
+        self.inner
+            .iter_chunks_mut(self.world.inner_mut())
+            .nth(index)
+    }
+
+    /// Returns the `index`th chunk matched by this query through a shared
+    /// reference, allowing chunks to be fetched from multiple threads.
+    ///
+    /// Like `get_chunk`, this walks the first `index` chunks. To split work
+    /// across `n` tasks, give each task a disjoint range of indices, e.g.
+    /// every `n`th index starting at its own, so each chunk is fetched once.
+    ///
+    /// # Safety
+    /// Each chunk index may only be fetched once at a time, and the world
+    /// may not be structurally modified while any chunk is alive.
+    pub unsafe fn get_chunk_unchecked(&self, index: usize) -> Option<Chunk<'_, Q::Legion>> {
+        self.inner
+            .iter_chunks_unchecked(self.world.inner())
+            .nth(index)
+    }
+
+    /// Returns the components of the only entity matched by this query.
+    ///
+    /// Panics if no entities or more than one entity matched.
+    pub fn single(&mut self) -> <<Q::Legion as View>::Iter as Iterator>::Item
+    where
+        Q::Legion: ReadOnly,
+    {
+        self.try_single()
+            .unwrap_or_else(|e| panic!("{} ({})", e, std::any::type_name::<Q>()))
+    }
+
+    /// Returns the components of the only entity matched by this query.
+    ///
+    /// Returns an error if no entities or more than one entity matched.
+    pub fn try_single(
+        &mut self,
+    ) -> Result<<<Q::Legion as View>::Iter as Iterator>::Item, QuerySingleError>
+    where
+        Q::Legion: ReadOnly,
+    {
+        single_item(self.inner.iter(self.world.inner()))
+    }
+
+    /// Mutably returns the components of the only entity matched by this query.
+    ///
+    /// Panics if no entities or more than one entity matched.
+    pub fn single_mut(&mut self) -> <<Q::Legion as View>::Iter as Iterator>::Item {
+        self.try_single_mut()
+            .unwrap_or_else(|e| panic!("{} ({})", e, std::any::type_name::<Q>()))
+    }
+
+    /// Mutably returns the components of the only entity matched by this query.
+    ///
+    /// Returns an error if no entities or more than one entity matched.
+    pub fn try_single_mut(
+        &mut self,
+    ) -> Result<<<Q::Legion as View>::Iter as Iterator>::Item, QuerySingleError> {
+        single_item(self.iter_mut())
+    }
+
+    /// Calls `f` for each item matched by this query until it returns `ControlFlow::Break`.
+    ///
+    /// Chunks after the one containing the breaking item are never visited.
+    pub fn try_for_each<B, G>(&mut self, mut f: G) -> ControlFlow<B>
+    where
+        G: FnMut(<<Q::Legion as View>::Iter as Iterator>::Item) -> ControlFlow<B>,
+    {
+        for item in self.iter_mut() {
+            if let ControlFlow::Break(b) = f(item) {
+                return ControlFlow::Break(b);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Calls `f` for each item matched by this query, stopping at the first error.
+    pub fn try_for_each_fallible<E, G>(&mut self, f: G) -> Result<(), E>
+    where
+        G: FnMut(<<Q::Legion as View>::Iter as Iterator>::Item) -> Result<(), E>,
+    {
+        self.iter_mut().try_for_each(f)
+    }
+
+    /// Returns an iterator over all unique, unordered combinations
+    /// of `N` entities matched by this query.
+    ///
+    /// The matched components are collected up front, so this allocates
+    /// proportionally to the number of matched entities.
+    pub fn iter_combinations<const N: usize>(
+        &mut self,
+    ) -> QueryCombinations<<<Q::Legion as View>::Iter as Iterator>::Item, N> {
+        QueryCombinations::new(self.iter_mut().collect())
+    }
+}
+
+/// Iterator over unique combinations of `N` items from a query,
+/// returned by `QueryBorrow::iter_combinations`.
+///
+/// The items of a combination are mutably borrowed at the same time,
+/// so they cannot outlive the next call. Iterate with
+/// `while let Some([a, b]) = combinations.fetch_next() { ... }`.
+pub struct QueryCombinations<T, const N: usize> {
+    items: Vec<T>,
+    indices: [usize; N],
+    started: bool,
+    done: bool,
+}
+
+impl<T, const N: usize> QueryCombinations<T, N> {
+    fn new(items: Vec<T>) -> Self {
+        let mut indices = [0; N];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = i;
+        }
+
+        Self {
+            items,
+            indices,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Returns the next combination, or `None` if all combinations
+    /// have been yielded.
+    pub fn fetch_next(&mut self) -> Option<[&mut T; N]> {
+        if !self.advance() {
+            return None;
+        }
+
+        let ptr = self.items.as_mut_ptr();
+        // Safety: `indices` is strictly increasing and in bounds,
+        // so the references never alias.
+        Some(self.indices.map(|i| unsafe { &mut *ptr.add(i) }))
+    }
+
+    /// Advances `indices` to the next combination in lexicographic order.
+    fn advance(&mut self) -> bool {
+        if self.done {
+            return false;
+        }
+
+        let len = self.items.len();
+        if !self.started {
+            self.started = true;
+            self.done = N > len;
+            return !self.done;
+        }
+
+        for i in (0..N).rev() {
+            if self.indices[i] < len - N + i {
+                self.indices[i] += 1;
+                for j in i + 1..N {
+                    self.indices[j] = self.indices[j - 1] + 1;
+                }
+                return true;
+            }
+        }
+
+        self.done = true;
+        false
+    }
+}
+
+fn single_item<I>(mut iter: I) -> Result<I::Item, QuerySingleError>
+where
+    I: Iterator,
+{
+    let item = iter.next().ok_or(QuerySingleError::NoEntities)?;
+    if iter.next().is_some() {
+        return Err(QuerySingleError::MultipleEntities);
+    }
+    Ok(item)
+}
+
+/// The component types accessed by a query.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Component types which are only read.
+    pub reads: Vec<ComponentTypeId>,
+    /// Component types which are written.
+    pub writes: Vec<ComponentTypeId>,
+}
+
+impl Access {
+    /// Returns whether the two accesses cannot be performed at the same time,
+    /// i.e. whether either writes a component the other accesses.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.writes
+            .iter()
+            .any(|ty| other.reads.contains(ty) || other.writes.contains(ty))
+            || other.writes.iter().any(|ty| self.reads.contains(ty))
+    }
+
+    /// Adds all component types accessed by `other` to this access.
+    pub fn extend(&mut self, other: &Access) {
+        for ty in &other.writes {
+            if !self.writes.contains(ty) {
+                self.writes.push(*ty);
+            }
+        }
+        for ty in &other.reads {
+            if !self.reads.contains(ty) {
+                self.reads.push(*ty);
+            }
+        }
+        let writes = &self.writes;
+        self.reads.retain(|ty| !writes.contains(ty));
+    }
 }
 
+/// A set of components which can be queried for.
+///
+/// Implemented for `&T` and `&mut T` where `T` is a component,
+/// and for flat tuples of those with up to 16 elements.
 pub trait Query {
     type Legion: IntoQuery;
+
+    /// Returns the component types read and written by this query.
+    fn access() -> Access {
+        let writes = <Self::Legion as View<'static>>::write_types();
+        let mut reads = <Self::Legion as View<'static>>::read_types();
+        reads.retain(|ty| !writes.contains(ty));
+        Access { reads, writes }
+    }
 }
 
 pub trait QueryElement {
@@ -57,6 +401,43 @@ where
     type Legion = Write<T>;
 }
 
+/// A `Query` whose items can be cloned into owned values.
+pub trait ClonedQuery: Query {
+    /// The owned form of an item, e.g. `(A, B)` for `(&A, &mut B)`.
+    type Owned;
+
+    fn clone_item(item: &<<Self::Legion as View<'_>>::Iter as Iterator>::Item) -> Self::Owned;
+}
+
+/// A `QueryElement` whose items can be cloned into owned values.
+pub trait ClonedQueryElement: QueryElement {
+    type Owned;
+
+    fn clone_item(item: &<<Self::Legion as View<'_>>::Iter as Iterator>::Item) -> Self::Owned;
+}
+
+impl<'a, T> ClonedQueryElement for &'a T
+where
+    T: Component + Clone,
+{
+    type Owned = T;
+
+    fn clone_item(item: &<<Self::Legion as View<'_>>::Iter as Iterator>::Item) -> Self::Owned {
+        T::clone(item)
+    }
+}
+
+impl<'a, T> ClonedQueryElement for &'a mut T
+where
+    T: Component + Clone,
+{
+    type Owned = T;
+
+    fn clone_item(item: &<<Self::Legion as View<'_>>::Iter as Iterator>::Item) -> Self::Owned {
+        T::clone(item)
+    }
+}
+
 macro_rules! recursive_macro_call_on_tuple {
     ($m: ident, $ty: ident) => {
         $m!{$ty}
@@ -76,4 +457,38 @@ macro_rules! impl_query {
     }
 }
 
-recursive_macro_call_on_tuple!(impl_query, A, B, C, D, E);
+macro_rules! impl_cloned_query {
+    ($($ty:ident),+) => {
+        #[allow(unused_parens)]
+        impl <'a, $($ty: ClonedQueryElement,)*> ClonedQuery for ($($ty),*) {
+            type Owned = ($($ty::Owned),*);
+
+            #[allow(non_snake_case)]
+            fn clone_item(item: &<<Self::Legion as View<'_>>::Iter as Iterator>::Item) -> Self::Owned {
+                let ($($ty),*) = item;
+                ($(<$ty as ClonedQueryElement>::clone_item($ty)),*)
+            }
+        }
+    }
+}
+
+recursive_macro_call_on_tuple!(impl_query, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+recursive_macro_call_on_tuple!(
+    impl_cloned_query,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P
+);
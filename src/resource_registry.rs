@@ -0,0 +1,178 @@
+//! Serialization of selected resources. Requires the `serialize` feature.
+
+use crate::resources::Resource;
+use crate::OwnedResources;
+use serde::de::{DeserializeOwned, DeserializeSeed, Error as _, MapAccess, Visitor};
+use serde::ser::{Error as _, SerializeMap};
+use serde::{Deserializer, Serialize, Serializer};
+use std::any::TypeId;
+use std::fmt;
+
+type SerializeFn = fn(&dyn Resource) -> &dyn erased_serde::Serialize;
+type DeserializeFn =
+    fn(&mut dyn erased_serde::Deserializer) -> Result<Box<dyn Resource>, erased_serde::Error>;
+
+struct RegistryEntry {
+    name: &'static str,
+    type_id: TypeId,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+/// The set of resource types which are saved and loaded
+/// by `OwnedResources::serialize` and `OwnedResources::deserialize_into`.
+///
+/// Each type is registered under a name, which is used as its key in the
+/// serialized map and so must remain stable across versions.
+#[derive(Default)]
+pub struct ResourceRegistry {
+    entries: Vec<RegistryEntry>,
+}
+
+impl ResourceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the resource type `T` under the given name.
+    pub fn register<T>(&mut self, name: &'static str)
+    where
+        T: Resource + Serialize + DeserializeOwned,
+    {
+        self.entries.push(RegistryEntry {
+            name,
+            type_id: TypeId::of::<T>(),
+            serialize: serialize_resource::<T>,
+            deserialize: deserialize_resource::<T>,
+        });
+    }
+
+    /// Builder function to register a resource type.
+    pub fn with<T>(mut self, name: &'static str) -> Self
+    where
+        T: Resource + Serialize + DeserializeOwned,
+    {
+        self.register::<T>(name);
+        self
+    }
+
+    fn entry(&self, name: &str) -> Option<&RegistryEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
+
+fn serialize_resource<T>(resource: &dyn Resource) -> &dyn erased_serde::Serialize
+where
+    T: Resource + Serialize,
+{
+    resource
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("resource stored under the wrong type")
+}
+
+fn deserialize_resource<T>(
+    deserializer: &mut dyn erased_serde::Deserializer,
+) -> Result<Box<dyn Resource>, erased_serde::Error>
+where
+    T: Resource + DeserializeOwned,
+{
+    Ok(Box::new(erased_serde::deserialize::<T>(deserializer)?))
+}
+
+/// Serializes the registered resources in an `OwnedResources` as a map.
+///
+/// Returned by `OwnedResources::serialize`.
+pub struct SerializeResources<'a> {
+    resources: &'a OwnedResources,
+    registry: &'a ResourceRegistry,
+}
+
+impl<'a> Serialize for SerializeResources<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for entry in &self.registry.entries {
+            let resource = match self.resources.try_get_dyn(entry.type_id) {
+                Some(resource) => resource.map_err(S::Error::custom)?,
+                None => continue,
+            };
+            map.serialize_entry(entry.name, (entry.serialize)(&*resource))?;
+        }
+        map.end()
+    }
+}
+
+struct EntrySeed<'a>(&'a RegistryEntry);
+
+impl<'a, 'de> DeserializeSeed<'de> for EntrySeed<'a> {
+    type Value = Box<dyn Resource>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0.deserialize)(&mut erased).map_err(D::Error::custom)
+    }
+}
+
+struct ResourcesVisitor<'a> {
+    resources: &'a mut OwnedResources,
+    registry: &'a ResourceRegistry,
+}
+
+impl<'a, 'de> Visitor<'de> for ResourcesVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of resource names to resources")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        while let Some(name) = map.next_key::<String>()? {
+            let entry = self
+                .registry
+                .entry(&name)
+                .ok_or_else(|| A::Error::custom(format!("unregistered resource {}", name)))?;
+            let resource = map.next_value_seed(EntrySeed(entry))?;
+            self.resources.insert_boxed(entry.type_id, resource);
+        }
+        Ok(())
+    }
+}
+
+impl OwnedResources {
+    /// Returns a value which serializes the resources registered in
+    /// `registry` as a map from their names to their values.
+    ///
+    /// Registered resources which do not exist are skipped.
+    /// Serialization fails if a registered resource is mutably borrowed.
+    pub fn serialize<'a>(&'a self, registry: &'a ResourceRegistry) -> SerializeResources<'a> {
+        SerializeResources {
+            resources: self,
+            registry,
+        }
+    }
+
+    /// Deserializes resources written by `serialize` and inserts them,
+    /// replacing existing values of the same types.
+    pub fn deserialize_into<'de, D>(
+        &mut self,
+        registry: &ResourceRegistry,
+        deserializer: D,
+    ) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ResourcesVisitor {
+            resources: self,
+            registry,
+        })
+    }
+}
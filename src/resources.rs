@@ -1,130 +1,1614 @@
-use arrayvec::ArrayVec;
-use fxhash::{FxBuildHasher, FxHashMap};
+use crate::util::{ConfiguredBuildHasher, HashMap};
+use crate::WorldOptions;
+use once_cell::sync::OnceCell;
+use smallvec::SmallVec;
 use std::any::{Any, TypeId};
 use std::cell::UnsafeCell;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::panic::Location;
+#[cfg(debug_assertions)]
+use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ResourceError {
     #[error("resource {0} not found")]
     NotFound(&'static str),
+    #[error("resource {0} with key {1:?} not found")]
+    KeyNotFound(&'static str, String),
     #[error("resource already borrowed")]
     AlreadyBorrowed,
 }
 
 type Result<T> = std::result::Result<T, ResourceError>;
 
+/// Unwraps the result of borrowing the resource `T`. If it was already
+/// borrowed, the panic message includes where, when that is recorded.
+#[track_caller]
+fn expect_borrow<T, B>(
+    result: Result<B>,
+    borrowed_at: impl FnOnce() -> Option<&'static Location<'static>>,
+) -> B
+where
+    T: ?Sized,
+{
+    let name = std::any::type_name::<T>();
+    match result {
+        Ok(borrow) => borrow,
+        Err(ResourceError::AlreadyBorrowed) => match borrowed_at() {
+            Some(location) => panic!("resource {} already borrowed at {}", name, location),
+            None => panic!("resource {} already borrowed", name),
+        },
+        Err(error) => panic!("{}", error),
+    }
+}
+
 pub trait Resource: Send + Sync + Any + 'static {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T> Resource for T
+where
+    T: Send + Sync + Any + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+#[derive(Default, Debug)]
+#[doc(hidden)]
+pub struct BorrowFlag {
+    /// If set to `u32::max_value()`, the resource
+    /// is borrowed mutably; otherwise, it is set to the number of immutable
+    /// borrows currently existing.
+    state: AtomicU32,
+    /// Tasks waiting in `get_async` or `get_mut_async` for this
+    /// resource to be released.
+    #[cfg(feature = "async")]
+    wakers: Mutex<Vec<Waker>>,
+    /// Location of the most recent live borrow, used
+    /// to diagnose borrow conflicts.
+    #[cfg(debug_assertions)]
+    borrowed_at: AtomicPtr<Location<'static>>,
+}
+
+impl BorrowFlag {
+    /// Attempts to flag this value as mutably borrowed, returning
+    /// `true` if successful and `false` otherwise.
+    #[track_caller]
+    fn obtain_mutable(&self) -> bool {
+        let obtained = self
+            .state
+            .compare_and_swap(0, u32::max_value(), Ordering::AcqRel)
+            == 0;
+        if obtained {
+            self.record_borrow();
+        }
+        obtained
+    }
+
+    /// Marks this resource as not mutably borrowed.
+    fn release_mutable(&self) {
+        debug_assert_eq!(self.state.load(Ordering::Acquire), u32::max_value());
+        self.clear_borrow();
+        self.state.store(0, Ordering::Release);
+        #[cfg(feature = "async")]
+        self.wake_waiters();
+    }
+
+    /// Attempts to obtain an immutable borrow, returning `true` if successful
+    /// and `false` otherwise.
+    #[track_caller]
+    fn obtain_immutable(&self) -> bool {
+        loop {
+            let val = self.state.load(Ordering::Acquire);
+
+            if val == u32::max_value() {
+                return false;
+            }
+
+            if self.state.compare_and_swap(val, val + 1, Ordering::AcqRel) == val {
+                self.record_borrow();
+                return true;
+            }
+        }
+    }
+
+    /// Releases an immutable borrow.
+    fn release_immutable(&self) {
+        let previous = self.state.fetch_sub(1, Ordering::AcqRel);
+        if previous == 1 {
+            self.clear_borrow();
+            // Only mutable borrows can be waiting on immutable ones.
+            #[cfg(feature = "async")]
+            self.wake_waiters();
+        }
+    }
+
+    /// Returns the location of the most recent live borrow of this resource, if any.
+    #[cfg(debug_assertions)]
+    fn borrowed_at(&self) -> Option<&'static Location<'static>> {
+        unsafe { self.borrowed_at.load(Ordering::Acquire).as_ref() }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn borrowed_at(&self) -> Option<&'static Location<'static>> {
+        None
+    }
+
+    #[track_caller]
+    fn record_borrow(&self) {
+        #[cfg(debug_assertions)]
+        self.borrowed_at
+            .store(Location::caller() as *const _ as *mut _, Ordering::Release);
+    }
+
+    fn clear_borrow(&self) {
+        #[cfg(debug_assertions)]
+        self.borrowed_at
+            .store(std::ptr::null_mut(), Ordering::Release);
+    }
+
+    /// Registers a task to be woken once this resource is released.
+    #[cfg(feature = "async")]
+    fn register_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().expect("borrow flag wakers poisoned");
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn wake_waiters(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().expect("borrow flag wakers poisoned"));
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Future returned by `OwnedResources::get_async` and `get_mut_async`.
+#[cfg(feature = "async")]
+struct BorrowFuture<'a, T, R> {
+    resources: &'a OwnedResources,
+    borrow: fn(&'a ResourceEntry) -> Result<R>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, T, R> Future for BorrowFuture<'a, T, R>
+where
+    T: Resource,
+{
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let entry = match self.resources.entry::<T>() {
+            Ok(entry) => entry,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+
+        match (self.borrow)(entry) {
+            Err(ResourceError::AlreadyBorrowed) => (),
+            result => return Poll::Ready(result),
+        }
+
+        // Register before trying again so that a release
+        // between the two attempts is not missed.
+        entry.flag.register_waker(cx.waker());
+        match (self.borrow)(entry) {
+            Err(ResourceError::AlreadyBorrowed) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+pub struct Ref<'a, T: ?Sized> {
+    flag: &'a BorrowFlag,
+    value: &'a T,
+}
+
+impl<'a, T: ?Sized> Deref for Ref<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for Ref<'a, T> {
+    fn drop(&mut self) {
+        self.flag.release_immutable();
+    }
+}
+
+pub struct RefMut<'a, T: ?Sized> {
+    flag: &'a BorrowFlag,
+    value: &'a mut T,
+}
+
+impl<'a, T: ?Sized> Deref for RefMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RefMut<'a, T> {
+    fn drop(&mut self) {
+        self.flag.release_mutable();
+    }
+}
+
+pub trait ResourcesProvider {
+    /// Immutably borrows a resource from this container.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist or if it
+    /// is already mutably borrowed.
+    #[track_caller]
+    fn get<T>(&self) -> Ref<T>
+    where
+        T: Resource;
+
+    /// Immutably borrows a resource from this container.
+    ///
+    /// Returns `None` if the resource does not exist
+    /// or if it is already mutably borrowed.
+    #[track_caller]
+    fn try_get<T>(&self) -> Result<Ref<T>>
+    where
+        T: Resource;
+
+    /// Mutably borrows a resource from this container.
+    ///
+    /// # Panics
+    /// Panics of the resource does not exist or it is already borrowed.
+    #[track_caller]
+    fn get_mut<T>(&self) -> RefMut<T>
+    where
+        T: Resource;
+
+    /// Mutably borrows a resource from this container.
+    ///
+    /// Returns `None` if the resource does not exist
+    /// or it is already borrowed.
+    #[track_caller]
+    fn try_get_mut<T>(&self) -> Result<RefMut<T>>
+    where
+        T: Resource;
+
+    /// Immutably borrows a keyed resource from this container.
+    ///
+    /// Returns an error if the resource does not exist
+    /// or if it is already mutably borrowed.
+    #[track_caller]
+    fn try_get_keyed<T>(&self, key: &str) -> Result<Ref<T>>
+    where
+        T: Resource;
+
+    /// Mutably borrows a keyed resource from this container.
+    ///
+    /// Returns an error if the resource does not exist
+    /// or if it is already borrowed.
+    #[track_caller]
+    fn try_get_keyed_mut<T>(&self, key: &str) -> Result<RefMut<T>>
+    where
+        T: Resource;
+
+    /// Immutably borrows a keyed resource from this container.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist or if it
+    /// is already mutably borrowed.
+    #[track_caller]
+    fn get_keyed<T>(&self, key: &str) -> Ref<T>
+    where
+        T: Resource,
+    {
+        self.try_get_keyed(key).unwrap()
+    }
+
+    /// Mutably borrows a keyed resource from this container.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist or it is already borrowed.
+    #[track_caller]
+    fn get_keyed_mut<T>(&self, key: &str) -> RefMut<T>
+    where
+        T: Resource,
+    {
+        self.try_get_keyed_mut(key).unwrap()
+    }
+
+    /// Immutably borrows a resource through a `ResourceId`.
+    ///
+    /// Returns an error if the resource does not exist
+    /// or if it is already mutably borrowed.
+    #[track_caller]
+    fn try_get_by_id<T>(&self, _id: ResourceId<T>) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        self.try_get()
+    }
+
+    /// Mutably borrows a resource through a `ResourceId`.
+    ///
+    /// Returns an error if the resource does not exist
+    /// or if it is already borrowed.
+    #[track_caller]
+    fn try_get_mut_by_id<T>(&self, _id: ResourceId<T>) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        self.try_get_mut()
+    }
+
+    /// Immutably borrows a resource through a `ResourceId`.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist or if it
+    /// is already mutably borrowed.
+    #[track_caller]
+    fn get_by_id<T>(&self, id: ResourceId<T>) -> Ref<T>
+    where
+        T: Resource,
+    {
+        self.try_get_by_id(id).unwrap()
+    }
+
+    /// Mutably borrows a resource through a `ResourceId`.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist or it is already borrowed.
+    #[track_caller]
+    fn get_mut_by_id<T>(&self, id: ResourceId<T>) -> RefMut<T>
+    where
+        T: Resource,
+    {
+        self.try_get_mut_by_id(id).unwrap()
+    }
+
+    /// Borrows several resources at once. `F` is a tuple with an element
+    /// for each resource: `&T` to borrow immutably or `&mut T` to borrow mutably.
+    ///
+    /// Returns an error if any resource does not exist or cannot be borrowed,
+    /// in which case no borrows are held.
+    #[track_caller]
+    fn try_get_many<'a, F>(&'a self) -> Result<F::Output>
+    where
+        F: ResourceFetch<'a>,
+        Self: Sized,
+    {
+        F::fetch(self)
+    }
+
+    /// Borrows several resources at once. See `try_get_many`.
+    ///
+    /// # Panics
+    /// Panics if any resource does not exist or cannot be borrowed.
+    #[track_caller]
+    fn get_many<'a, F>(&'a self) -> F::Output
+    where
+        F: ResourceFetch<'a>,
+        Self: Sized,
+    {
+        self.try_get_many::<F>().unwrap()
+    }
+
+    /// Converts this `ResourcesProvider` into a `ResourcesRef`
+    /// suitable for passing to dynamically-dispatched functions.
+    fn as_resources_ref(&self) -> ResourcesEnum;
+}
+
+/// A set of resource borrows which can be obtained with
+/// `ResourcesProvider::get_many`.
+///
+/// Implemented for `&T` (yielding `Ref<T>`), `&mut T` (yielding `RefMut<T>`),
+/// and tuples of those with up to 12 elements.
+pub trait ResourceFetch<'a> {
+    type Output;
+
+    #[track_caller]
+    fn fetch<R>(resources: &'a R) -> Result<Self::Output>
+    where
+        R: ResourcesProvider;
+}
+
+impl<'a, 'r, T> ResourceFetch<'a> for &'r T
+where
+    T: Resource,
+{
+    type Output = Ref<'a, T>;
+
+    fn fetch<R>(resources: &'a R) -> Result<Self::Output>
+    where
+        R: ResourcesProvider,
+    {
+        resources.try_get()
+    }
+}
+
+impl<'a, 'r, T> ResourceFetch<'a> for &'r mut T
+where
+    T: Resource,
+{
+    type Output = RefMut<'a, T>;
+
+    fn fetch<R>(resources: &'a R) -> Result<Self::Output>
+    where
+        R: ResourcesProvider,
+    {
+        resources.try_get_mut()
+    }
+}
+
+macro_rules! impl_resource_fetch {
+    ($($ty:ident),*) => {
+        impl <'a, $($ty,)*> ResourceFetch<'a> for ($($ty,)*) where $($ty: ResourceFetch<'a>,)* {
+            type Output = ($($ty::Output,)*);
+
+            fn fetch<R>(resources: &'a R) -> Result<Self::Output>
+            where
+                R: ResourcesProvider,
+            {
+                Ok(($($ty::fetch(resources)?,)*))
+            }
+        }
+    }
 }
 
-impl<T> Resource for T
-where
-    T: Send + Sync + Any + 'static,
-{
-    fn as_any(&self) -> &dyn Any {
-        self
+impl_resource_fetch!(A);
+impl_resource_fetch!(A, B);
+impl_resource_fetch!(A, B, C);
+impl_resource_fetch!(A, B, C, D);
+impl_resource_fetch!(A, B, C, D, E);
+impl_resource_fetch!(A, B, C, D, E, F);
+impl_resource_fetch!(A, B, C, D, E, F, G);
+impl_resource_fetch!(A, B, C, D, E, F, G, H);
+impl_resource_fetch!(A, B, C, D, E, F, G, H, I);
+impl_resource_fetch!(A, B, C, D, E, F, G, H, I, J);
+impl_resource_fetch!(A, B, C, D, E, F, G, H, I, J, K);
+impl_resource_fetch!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// A set of resources which can be inserted at once with
+/// `OwnedResources::insert_bundle`.
+///
+/// Implemented for tuples of resources with up to 16 elements.
+pub trait ResourceBundle {
+    fn insert_into(self, resources: &mut OwnedResources);
+}
+
+macro_rules! impl_resource_bundle {
+    ($($ty:ident),*) => {
+        impl <$($ty,)*> ResourceBundle for ($($ty,)*) where $($ty: Resource,)* {
+            #[allow(non_snake_case)]
+            fn insert_into(self, resources: &mut OwnedResources) {
+                let ($($ty,)*) = self;
+                $(
+                    resources.insert($ty);
+                )*
+            }
+        }
+    }
+}
+
+impl_resource_bundle!(A);
+impl_resource_bundle!(A, B);
+impl_resource_bundle!(A, B, C);
+impl_resource_bundle!(A, B, C, D);
+impl_resource_bundle!(A, B, C, D, E);
+impl_resource_bundle!(A, B, C, D, E, F);
+impl_resource_bundle!(A, B, C, D, E, F, G);
+impl_resource_bundle!(A, B, C, D, E, F, G, H);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K, L);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K, L, M);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
+impl_resource_bundle!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+pub enum ResourcesEnum<'a> {
+    Owned(&'a OwnedResources),
+    Ref(&'a RefResources<'a, OwnedResources>),
+    DoubleRef(&'a ResourcesEnum<'a>),
+}
+
+impl<'a> ResourcesEnum<'a> {
+    /// Queues the resource `T` for removal by the next call to
+    /// `OwnedResources::apply_queued` on the underlying resources.
+    ///
+    /// This allows systems, which take a `&ResourcesEnum`, to remove resources.
+    pub fn queue_remove<T>(&self)
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.queue_remove::<T>(),
+            ResourcesEnum::Ref(res) => res.inner.queue_remove::<T>(),
+            ResourcesEnum::DoubleRef(res) => res.queue_remove::<T>(),
+        }
+    }
+}
+
+impl<'a> ResourcesProvider for ResourcesEnum<'a> {
+    fn get<T>(&self) -> Ref<T>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.get(),
+            ResourcesEnum::Ref(res) => res.get(),
+            ResourcesEnum::DoubleRef(res) => res.get(),
+        }
+    }
+
+    fn try_get<T>(&self) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get(),
+            ResourcesEnum::Ref(res) => res.try_get(),
+            ResourcesEnum::DoubleRef(res) => res.try_get(),
+        }
+    }
+
+    fn get_mut<T>(&self) -> RefMut<T>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.get_mut(),
+            ResourcesEnum::Ref(res) => res.get_mut(),
+            ResourcesEnum::DoubleRef(res) => res.get_mut(),
+        }
+    }
+
+    fn try_get_mut<T>(&self) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get_mut(),
+            ResourcesEnum::Ref(res) => res.try_get_mut(),
+            ResourcesEnum::DoubleRef(res) => res.try_get_mut(),
+        }
+    }
+
+    fn try_get_keyed<T>(&self, key: &str) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get_keyed(key),
+            ResourcesEnum::Ref(res) => res.try_get_keyed(key),
+            ResourcesEnum::DoubleRef(res) => res.try_get_keyed(key),
+        }
+    }
+
+    fn try_get_keyed_mut<T>(&self, key: &str) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get_keyed_mut(key),
+            ResourcesEnum::Ref(res) => res.try_get_keyed_mut(key),
+            ResourcesEnum::DoubleRef(res) => res.try_get_keyed_mut(key),
+        }
+    }
+
+    fn try_get_by_id<T>(&self, id: ResourceId<T>) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get_by_id(id),
+            ResourcesEnum::Ref(res) => res.try_get_by_id(id),
+            ResourcesEnum::DoubleRef(res) => res.try_get_by_id(id),
+        }
+    }
+
+    fn try_get_mut_by_id<T>(&self, id: ResourceId<T>) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        match self {
+            ResourcesEnum::Owned(res) => res.try_get_mut_by_id(id),
+            ResourcesEnum::Ref(res) => res.try_get_mut_by_id(id),
+            ResourcesEnum::DoubleRef(res) => res.try_get_mut_by_id(id),
+        }
+    }
+
+    fn as_resources_ref(&self) -> ResourcesEnum {
+        ResourcesEnum::DoubleRef(self)
+    }
+}
+
+/// A single value stored in `OwnedResources`.
+struct ResourceEntry {
+    flag: BorrowFlag,
+    /// Set when the resource is inserted or mutably borrowed.
+    changed: AtomicBool,
+    value: UnsafeCell<Box<dyn Resource>>,
+}
+
+impl ResourceEntry {
+    fn new(value: Box<dyn Resource>) -> Self {
+        Self {
+            flag: BorrowFlag::default(),
+            changed: AtomicBool::new(true),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[track_caller]
+    fn borrow<T>(&self) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        if self.flag.obtain_immutable() {
+            Ok(Ref {
+                flag: &self.flag,
+                value: Box::deref(unsafe { &*self.value.get() })
+                    .as_any()
+                    .downcast_ref()
+                    .unwrap(),
+            })
+        } else {
+            Err(ResourceError::AlreadyBorrowed)
+        }
+    }
+
+    #[track_caller]
+    fn borrow_mut<T>(&self) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        if self.flag.obtain_mutable() {
+            self.changed.store(true, Ordering::Release);
+            Ok(RefMut {
+                flag: &self.flag,
+                value: Box::deref_mut(unsafe { &mut *self.value.get() })
+                    .as_any_mut()
+                    .downcast_mut()
+                    .unwrap(),
+            })
+        } else {
+            Err(ResourceError::AlreadyBorrowed)
+        }
+    }
+
+    #[track_caller]
+    fn borrow_dyn(&self) -> Result<Ref<dyn Resource>> {
+        if self.flag.obtain_immutable() {
+            Ok(Ref {
+                flag: &self.flag,
+                value: Box::deref(unsafe { &*self.value.get() }),
+            })
+        } else {
+            Err(ResourceError::AlreadyBorrowed)
+        }
+    }
+
+    fn into_inner<T>(self) -> T
+    where
+        T: Resource,
+    {
+        *self
+            .value
+            .into_inner()
+            .into_any()
+            .downcast::<T>()
+            .expect("resource stored under the wrong type")
+    }
+}
+
+type Constructor = Box<dyn FnOnce() -> Box<dyn Resource> + Send>;
+
+/// A resource registered with `OwnedResources::register_lazy`
+/// which is constructed on first access.
+struct LazyEntry {
+    entry: OnceCell<ResourceEntry>,
+    constructor: Mutex<Option<Constructor>>,
+}
+
+impl LazyEntry {
+    fn force(&self) -> &ResourceEntry {
+        self.entry.get_or_init(|| {
+            let constructor = self
+                .constructor
+                .lock()
+                .expect("lazy resource constructor poisoned")
+                .take()
+                .expect("lazy resource constructor already ran");
+            ResourceEntry::new(constructor())
+        })
+    }
+}
+
+/// Storage for the resources in `OwnedResources`, indexed by type.
+///
+/// Each type is assigned a slot when first inserted. Slots are kept
+/// when a resource is removed, so a `ResourceId` stays valid for
+/// the lifetime of the container.
+struct ResourceSlots {
+    indices: HashMap<TypeId, usize>,
+    slots: Vec<(TypeId, Option<ResourceEntry>)>,
+}
+
+impl ResourceSlots {
+    fn with_hasher(hasher: ConfiguredBuildHasher) -> Self {
+        Self {
+            indices: HashMap::with_hasher(hasher),
+            slots: Vec::new(),
+        }
+    }
+
+    /// Returns the slot for `type_id`, allocating one if needed.
+    fn reserve(&mut self, type_id: TypeId) -> usize {
+        let slots = &mut self.slots;
+        *self.indices.entry(type_id).or_insert_with(|| {
+            slots.push((type_id, None));
+            slots.len() - 1
+        })
+    }
+
+    fn get(&self, type_id: &TypeId) -> Option<&ResourceEntry> {
+        self.indices
+            .get(type_id)
+            .and_then(|&index| self.slots[index].1.as_ref())
+    }
+
+    /// Returns the entry in the slot at `index` if it holds `type_id`.
+    fn get_index(&self, index: usize, type_id: TypeId) -> Option<&ResourceEntry> {
+        self.slots
+            .get(index)
+            .filter(|(id, _)| *id == type_id)
+            .and_then(|(_, entry)| entry.as_ref())
+    }
+
+    fn contains_key(&self, type_id: &TypeId) -> bool {
+        self.get(type_id).is_some()
+    }
+
+    fn insert(&mut self, type_id: TypeId, entry: ResourceEntry) -> Option<ResourceEntry> {
+        let index = self.reserve(type_id);
+        self.slots[index].1.replace(entry)
+    }
+
+    fn remove(&mut self, type_id: &TypeId) -> Option<ResourceEntry> {
+        let index = *self.indices.get(type_id)?;
+        self.slots[index].1.take()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &TypeId> {
+        self.slots
+            .iter()
+            .filter(|(_, entry)| entry.is_some())
+            .map(|(type_id, _)| type_id)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &ResourceEntry> {
+        self.slots.iter().filter_map(|(_, entry)| entry.as_ref())
+    }
+
+    /// Removes and returns all entries, keeping their slots.
+    fn drain(&mut self) -> impl Iterator<Item = (TypeId, ResourceEntry)> + '_ {
+        self.slots
+            .iter_mut()
+            .filter_map(|(type_id, entry)| entry.take().map(|entry| (*type_id, entry)))
+    }
+}
+
+/// A handle to the resource `T` in an `OwnedResources`, obtained
+/// with `OwnedResources::resource_id`.
+///
+/// Borrowing through a `ResourceId` indexes the container
+/// directly instead of hashing the `TypeId` of `T`.
+pub struct ResourceId<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for ResourceId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ResourceId<T> {}
+
+type Hook = Box<dyn Fn(&mut dyn Resource) + Send + Sync>;
+
+fn clone_resource<T>(resource: &dyn Resource) -> Box<dyn Resource>
+where
+    T: Resource + Clone,
+{
+    Box::new(
+        resource
+            .as_any()
+            .downcast_ref::<T>()
+            .expect("resource stored under the wrong type")
+            .clone(),
+    )
+}
+
+/// Callbacks registered with `OwnedResources::on_insert` and `on_remove`.
+#[derive(Default)]
+struct ResourceHooks {
+    on_insert: Vec<Hook>,
+    on_remove: Vec<Hook>,
+}
+
+/// Stores a set of owned values, each with a distinct type.
+///
+/// Resources are borrow checked at runtime.
+pub struct OwnedResources {
+    /// Mapping from resource types to their structs.
+    types: ResourceSlots,
+    /// Mapping from resource types to shared resources
+    /// inserted with `insert_arc`.
+    arcs: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    /// Mapping from resource types to resources inserted with
+    /// `insert_keyed`, indexed by key.
+    keyed: HashMap<TypeId, HashMap<String, ResourceEntry>>,
+    /// Resources registered with `register_lazy`. Once constructed,
+    /// they behave like those in `types`.
+    lazy: HashMap<TypeId, LazyEntry>,
+    /// Resources inserted through `&self` with `insert_shared`. Entries
+    /// are boxed so that borrows stay valid while others are inserted;
+    /// they are moved into `types` by the next method taking `&mut self`.
+    shared: Mutex<HashMap<TypeId, Box<ResourceEntry>>>,
+    /// Lifecycle hooks for each resource type.
+    hooks: HashMap<TypeId, ResourceHooks>,
+    /// Functions cloning resources registered with `register_clone`.
+    cloners: HashMap<TypeId, fn(&dyn Resource) -> Box<dyn Resource>>,
+    /// Resources queued for removal with `queue_remove`.
+    queued_removals: Mutex<Vec<TypeId>>,
+}
+
+// Safety: we ensure correct resource borrows through the atomic `BorrowFlag`.
+unsafe impl Send for OwnedResources {}
+unsafe impl Sync for OwnedResources {}
+
+impl Drop for OwnedResources {
+    fn drop(&mut self) {
+        // Run `on_remove` hooks for the remaining resources.
+        if !self.hooks.is_empty() {
+            self.clear();
+        }
+    }
+}
+
+impl Default for OwnedResources {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OwnedResources {
+    /// Creates a new `Resources` with no stored values.
+    pub fn new() -> Self {
+        Self::with_options(&WorldOptions::default())
+    }
+
+    /// Creates a new `Resources` with no stored values, using
+    /// the hasher selected in `options`.
+    pub fn with_options(options: &WorldOptions) -> Self {
+        Self::with_hasher(ConfiguredBuildHasher::new(options.hasher))
+    }
+
+    fn with_hasher(hasher: ConfiguredBuildHasher) -> Self {
+        Self {
+            types: ResourceSlots::with_hasher(hasher.clone()),
+            arcs: HashMap::with_hasher(hasher.clone()),
+            keyed: HashMap::with_hasher(hasher.clone()),
+            lazy: HashMap::with_hasher(hasher.clone()),
+            shared: Mutex::new(HashMap::with_hasher(hasher.clone())),
+            hooks: HashMap::with_hasher(hasher.clone()),
+            cloners: HashMap::with_hasher(hasher),
+            queued_removals: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Inserts a new resource into this `Resources`.
+    ///
+    /// Replaces an existing value of the same type.
+    pub fn insert<T>(&mut self, resource: T)
+    where
+        T: Resource,
+    {
+        self.insert_entry(TypeId::of::<T>(), Box::new(resource));
+    }
+
+    /// Registers a callback invoked with each value of the resource `T`
+    /// when it is inserted with `insert`, `insert_bundle`, or `insert_shared`.
+    pub fn on_insert<T>(&mut self, hook: impl Fn(&mut T) + Send + Sync + 'static)
+    where
+        T: Resource,
+    {
+        self.hooks
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .on_insert
+            .push(Self::erase_hook(hook));
+    }
+
+    /// Registers a callback invoked with each value of the resource `T`
+    /// when it is removed, replaced, or cleared from this `Resources`,
+    /// including when the `Resources` is dropped.
+    pub fn on_remove<T>(&mut self, hook: impl Fn(&mut T) + Send + Sync + 'static)
+    where
+        T: Resource,
+    {
+        self.hooks
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .on_remove
+            .push(Self::erase_hook(hook));
+    }
+
+    fn erase_hook<T>(hook: impl Fn(&mut T) + Send + Sync + 'static) -> Hook
+    where
+        T: Resource,
+    {
+        Box::new(move |resource: &mut dyn Resource| {
+            hook(
+                resource
+                    .as_any_mut()
+                    .downcast_mut()
+                    .expect("resource stored under the wrong type"),
+            )
+        })
+    }
+
+    fn run_hooks(
+        &self,
+        type_id: TypeId,
+        resource: &mut dyn Resource,
+        select: fn(&ResourceHooks) -> &[Hook],
+    ) {
+        if let Some(hooks) = self.hooks.get(&type_id) {
+            for hook in select(hooks) {
+                hook(&mut *resource);
+            }
+        }
+    }
+
+    /// Stores a resource under `type_id`, running `on_insert` hooks
+    /// and `on_remove` hooks for any value it replaces.
+    fn insert_entry(&mut self, type_id: TypeId, mut resource: Box<dyn Resource>) {
+        self.remove_entry(type_id);
+        self.run_hooks(type_id, &mut *resource, |hooks| &hooks.on_insert);
+        self.types.insert(type_id, ResourceEntry::new(resource));
+    }
+
+    /// Removes the resource stored under `type_id`, including
+    /// a constructed lazy resource, running `on_remove` hooks.
+    fn remove_entry(&mut self, type_id: TypeId) -> Option<ResourceEntry> {
+        self.apply_shared();
+        let mut entry = self.types.remove(&type_id).or_else(|| {
+            self.lazy
+                .remove(&type_id)
+                .and_then(|lazy| lazy.entry.into_inner())
+        })?;
+        self.run_hooks(type_id, &mut **entry.value.get_mut(), |hooks| {
+            &hooks.on_remove
+        });
+        Some(entry)
+    }
+
+    /// Inserts each resource in `bundle`, a tuple of resources.
+    ///
+    /// Replaces existing values of the same types.
+    pub fn insert_bundle(&mut self, bundle: impl ResourceBundle) {
+        bundle.insert_into(self);
+    }
+
+    /// Inserts a new resource through a shared reference, so that
+    /// resources can be added while others are borrowed, e.g. by
+    /// plugins loaded on another thread.
+    ///
+    /// Unlike `insert`, this never replaces an existing value; if a
+    /// resource of the same type exists, `resource` is returned as an error.
+    pub fn insert_shared<T>(&self, resource: T) -> std::result::Result<(), T>
+    where
+        T: Resource,
+    {
+        let type_id = TypeId::of::<T>();
+        let mut shared = self.shared.lock().expect("shared resources poisoned");
+        if self.types.contains_key(&type_id)
+            || self.lazy.contains_key(&type_id)
+            || shared.contains_key(&type_id)
+        {
+            return Err(resource);
+        }
+
+        let mut resource: Box<dyn Resource> = Box::new(resource);
+        self.run_hooks(type_id, &mut *resource, |hooks| &hooks.on_insert);
+        shared.insert(type_id, Box::new(ResourceEntry::new(resource)));
+        Ok(())
+    }
+
+    /// Queues the resource `T` for removal by the next call to `apply_queued`.
+    ///
+    /// Unlike `remove`, this takes `&self`, so it can be called from systems,
+    /// also through `ResourcesEnum::queue_remove`.
+    pub fn queue_remove<T>(&self)
+    where
+        T: Resource,
+    {
+        self.queued_removals
+            .lock()
+            .expect("queued removals poisoned")
+            .push(TypeId::of::<T>());
+    }
+
+    /// Applies changes queued through `&self`: resources inserted with
+    /// `insert_shared` are stored normally and those queued with
+    /// `queue_remove` are removed.
+    ///
+    /// Executors only borrow the resources, so they never call this; the
+    /// application should call it between ticks, outside of any executor.
+    pub fn apply_queued(&mut self) {
+        self.apply_shared();
+        let removals = std::mem::take(
+            self.queued_removals
+                .get_mut()
+                .expect("queued removals poisoned"),
+        );
+        for type_id in removals {
+            self.remove_entry(type_id);
+        }
+    }
+
+    /// Moves resources inserted with `insert_shared` into `types`.
+    fn apply_shared(&mut self) {
+        let shared = self.shared.get_mut().expect("shared resources poisoned");
+        for (type_id, entry) in shared.drain() {
+            self.types.insert(type_id, *entry);
+        }
+    }
+
+    fn shared_entry(&self, type_id: TypeId) -> Option<&ResourceEntry> {
+        let shared = self.shared.lock().expect("shared resources poisoned");
+        // Safety: shared entries are boxed and only dropped or moved
+        // through `&mut self`, so the entry outlives this borrow.
+        shared
+            .get(&type_id)
+            .map(|entry| unsafe { &*(&**entry as *const ResourceEntry) })
+    }
+
+    /// Moves all resources from `other` into this `Resources`,
+    /// including keyed, shared, and lazily registered ones.
+    ///
+    /// Resources in `other` replace existing values of the same type.
+    pub fn extend(&mut self, mut other: OwnedResources) {
+        self.apply_shared();
+        other.apply_shared();
+        for (type_id, mut entry) in other.types.drain() {
+            self.remove_entry(type_id);
+            self.run_hooks(type_id, &mut **entry.value.get_mut(), |hooks| {
+                &hooks.on_insert
+            });
+            self.types.insert(type_id, entry);
+        }
+        for (type_id, lazy) in other.lazy.drain() {
+            self.remove_entry(type_id);
+            self.lazy.insert(type_id, lazy);
+        }
+        self.cloners.extend(other.cloners.drain());
+        for (type_id, hooks) in other.hooks.drain() {
+            let existing = self.hooks.entry(type_id).or_default();
+            existing.on_insert.extend(hooks.on_insert);
+            existing.on_remove.extend(hooks.on_remove);
+        }
+        self.arcs.extend(other.arcs.drain());
+        for (type_id, entries) in other.keyed.drain() {
+            self.keyed.entry(type_id).or_default().extend(entries);
+        }
+    }
+
+    /// Registers a constructor for the resource `T` which is invoked
+    /// the first time the resource is borrowed.
+    ///
+    /// Replaces an existing value of the same type.
+    pub fn register_lazy<T>(&mut self, constructor: impl FnOnce() -> T + Send + 'static)
+    where
+        T: Resource,
+    {
+        self.remove_entry(TypeId::of::<T>());
+        self.lazy.insert(
+            TypeId::of::<T>(),
+            LazyEntry {
+                entry: OnceCell::new(),
+                constructor: Mutex::new(Some(Box::new(move || {
+                    Box::new(constructor()) as Box<dyn Resource>
+                }))),
+            },
+        );
+    }
+
+    /// Immutably borrows a resource, waiting until any mutable
+    /// borrow is released instead of returning `AlreadyBorrowed`.
+    ///
+    /// The future resolves to an error if the resource does not exist.
+    #[cfg(feature = "async")]
+    pub fn get_async<T>(&self) -> impl Future<Output = Result<Ref<T>>> + '_
+    where
+        T: Resource,
+    {
+        BorrowFuture::<T, _> {
+            resources: self,
+            borrow: ResourceEntry::borrow::<T>,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Mutably borrows a resource, waiting until all other
+    /// borrows are released instead of returning `AlreadyBorrowed`.
+    ///
+    /// The future resolves to an error if the resource does not exist.
+    #[cfg(feature = "async")]
+    pub fn get_mut_async<T>(&self) -> impl Future<Output = Result<RefMut<T>>> + '_
+    where
+        T: Resource,
+    {
+        BorrowFuture::<T, _> {
+            resources: self,
+            borrow: ResourceEntry::borrow_mut::<T>,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a `ResourceId` for faster access to the resource `T`.
+    ///
+    /// The resource need not exist yet; the handle remains valid
+    /// as it is inserted and removed.
+    pub fn resource_id<T>(&mut self) -> ResourceId<T>
+    where
+        T: Resource,
+    {
+        self.apply_shared();
+        ResourceId {
+            index: self.types.reserve(TypeId::of::<T>()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Finds the entry for a resource by its `ResourceId`, falling
+    /// back to `entry` if the slot is empty.
+    fn entry_by_id<T>(&self, id: ResourceId<T>) -> Result<&ResourceEntry>
+    where
+        T: Resource,
+    {
+        match self.types.get_index(id.index, TypeId::of::<T>()) {
+            Some(entry) => Ok(entry),
+            None => self.entry::<T>(),
+        }
+    }
+
+    /// Returns where the resource `T` was most recently borrowed, if it is
+    /// currently borrowed, to diagnose a `ResourceError::AlreadyBorrowed`.
+    ///
+    /// Locations are only recorded in debug builds; in release
+    /// builds, this always returns `None`.
+    pub fn borrowed_at<T>(&self) -> Option<&'static Location<'static>>
+    where
+        T: Resource,
+    {
+        self.entry::<T>().ok()?.flag.borrowed_at()
+    }
+
+    /// Finds the entry for a resource, constructing it if it was registered lazily.
+    fn entry<T>(&self) -> Result<&ResourceEntry>
+    where
+        T: Resource,
+    {
+        let type_id = TypeId::of::<T>();
+        self.types
+            .get(&type_id)
+            .or_else(|| self.lazy.get(&type_id).map(LazyEntry::force))
+            .or_else(|| self.shared_entry(type_id))
+            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
+    }
+
+    /// Temporarily replaces the resource `T` with `temp` while `f` runs,
+    /// then restores the original value (or removes `temp` if there was none).
+    ///
+    /// The original is restored even if `f` panics.
+    pub fn scope<T, R>(&mut self, temp: T, f: impl FnOnce(&OwnedResources) -> R) -> R
+    where
+        T: Resource,
+    {
+        self.apply_shared();
+        let type_id = TypeId::of::<T>();
+        let original = self
+            .types
+            .insert(type_id, ResourceEntry::new(Box::new(temp)));
+
+        let guard = ScopeGuard {
+            resources: self,
+            type_id,
+            original,
+        };
+        f(&*guard.resources)
+    }
+
+    /// Inserts a resource distinguished from others of the same type by `key`.
+    ///
+    /// Keyed resources are separate from those inserted with `insert`
+    /// and are borrowed individually with `get_keyed` and `get_keyed_mut`.
+    ///
+    /// Replaces an existing value of the same type and key.
+    pub fn insert_keyed<T>(&mut self, key: impl Into<String>, resource: T)
+    where
+        T: Resource,
+    {
+        self.keyed
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .insert(key.into(), ResourceEntry::new(Box::new(resource)));
+    }
+
+    /// Removes a keyed resource, returning it.
+    ///
+    /// Returns `None` if the resource does not exist.
+    pub fn remove_keyed<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: Resource,
+    {
+        self.keyed
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|entries| entries.remove(key))
+            .map(ResourceEntry::into_inner)
+    }
+
+    /// Returns the keys of all resources of type `T` inserted with `insert_keyed`.
+    pub fn keys<T>(&self) -> impl Iterator<Item = &str>
+    where
+        T: Resource,
+    {
+        self.keyed
+            .get(&TypeId::of::<T>())
+            .into_iter()
+            .flat_map(|entries| entries.keys().map(String::as_str))
+    }
+
+    fn keyed_entry<T>(&self, key: &str) -> Result<&ResourceEntry>
+    where
+        T: Resource,
+    {
+        self.keyed
+            .get(&TypeId::of::<T>())
+            .and_then(|entries| entries.get(key))
+            .ok_or_else(|| ResourceError::KeyNotFound(std::any::type_name::<T>(), key.to_owned()))
+    }
+
+    /// Inserts a type-erased resource stored under `type_id`.
+    pub(crate) fn insert_boxed(&mut self, type_id: TypeId, resource: Box<dyn Resource>) {
+        self.insert_entry(type_id, resource);
+    }
+
+    /// Immutably borrows the type-erased resource stored under `type_id`.
+    ///
+    /// Returns `None` if the resource does not exist.
+    pub(crate) fn try_get_dyn(&self, type_id: TypeId) -> Option<Result<Ref<dyn Resource>>> {
+        self.types.get(&type_id).map(ResourceEntry::borrow_dyn)
+    }
+
+    /// Inserts a shared, read-only resource.
+    ///
+    /// Shared resources live separately from those inserted with `insert`
+    /// and are accessed with `get_arc`, which clones the `Arc` instead
+    /// of taking a borrow.
+    ///
+    /// Replaces an existing shared value of the same type.
+    pub fn insert_arc<T>(&mut self, resource: Arc<T>)
+    where
+        T: Send + Sync + 'static,
+    {
+        self.arcs.insert(TypeId::of::<T>(), resource);
+    }
+
+    /// Returns a clone of a shared resource inserted with `insert_arc`.
+    ///
+    /// # Panics
+    /// Panics if the shared resource does not exist.
+    pub fn get_arc<T>(&self) -> Arc<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.try_get_arc().unwrap()
+    }
+
+    /// Returns a clone of a shared resource inserted with `insert_arc`.
+    ///
+    /// Returns an error if the shared resource does not exist.
+    pub fn try_get_arc<T>(&self) -> Result<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.arcs
+            .get(&TypeId::of::<T>())
+            .map(|arc| {
+                Arc::clone(arc)
+                    .downcast()
+                    .expect("resource stored under the wrong type")
+            })
+            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
+    }
+
+    /// Removes a shared resource inserted with `insert_arc`, returning it.
+    pub fn remove_arc<T>(&mut self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.arcs.remove(&TypeId::of::<T>()).map(|arc| {
+            arc.downcast()
+                .expect("resource stored under the wrong type")
+        })
+    }
+
+    /// Mutably borrows a resource, first inserting the value
+    /// returned by `f` if the resource does not exist.
+    pub fn get_or_insert_with<T>(&mut self, f: impl FnOnce() -> T) -> RefMut<T>
+    where
+        T: Resource,
+    {
+        if !self.contains::<T>() {
+            self.insert(f());
+        }
+        self.get_mut()
+    }
+
+    /// Mutably borrows a resource, first inserting `T::default()`
+    /// if the resource does not exist.
+    pub fn get_or_default<T>(&mut self) -> RefMut<T>
+    where
+        T: Resource + Default,
+    {
+        self.get_or_insert_with(T::default)
+    }
+
+    /// Removes a resource from this `Resources`, returning it.
+    ///
+    /// Since this takes `&mut self`, the resource cannot be borrowed
+    /// while it is removed.
+    ///
+    /// Returns `None` if the resource does not exist.
+    pub fn remove<T>(&mut self) -> Option<T>
+    where
+        T: Resource,
+    {
+        self.remove_entry(TypeId::of::<T>())
+            .map(ResourceEntry::into_inner)
+    }
+
+    /// Returns whether a resource of type `T` exists in this `Resources`.
+    ///
+    /// Lazily registered resources exist even if they have not been constructed yet.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Resource,
+    {
+        self.contains_id(TypeId::of::<T>())
     }
 
-    fn as_any_mut(&mut self) -> &mut dyn Any {
-        self
+    /// Returns whether a resource with the given `TypeId` exists in this `Resources`.
+    pub(crate) fn contains_id(&self, type_id: TypeId) -> bool {
+        self.types.contains_key(&type_id)
+            || self.lazy.contains_key(&type_id)
+            || self.shared_entry(type_id).is_some()
     }
-}
 
-#[derive(Default, Debug)]
-#[doc(hidden)]
-pub struct BorrowFlag(
-    /// If set to `u32::max_value()`, the resource
-    /// is borrowed mutably; otherwise, it is set to the number of immutable
-    /// borrows currently existing.
-    AtomicU32,
-);
+    /// Removes all resources from this `Resources`,
+    /// including shared and keyed ones.
+    ///
+    /// Registered hooks are kept, and `on_remove` hooks
+    /// run for each removed resource.
+    pub fn clear(&mut self) {
+        self.apply_shared();
+        let type_ids: Vec<TypeId> = self.types.keys().chain(self.lazy.keys()).copied().collect();
+        for type_id in type_ids {
+            self.remove_entry(type_id);
+        }
+        self.lazy.clear();
+        self.arcs.clear();
+        self.keyed.clear();
+    }
 
-impl BorrowFlag {
-    /// Attempts to flag this value as mutably borrowed, returning
-    /// `true` if successful and `false` otherwise.
-    fn obtain_mutable(&self) -> bool {
-        self.0
-            .compare_and_swap(0, u32::max_value(), Ordering::AcqRel)
-            == 0
+    /// Returns whether the resource `T` was inserted or mutably
+    /// borrowed since the last call to `clear_changed`.
+    ///
+    /// Returns `false` if the resource does not exist.
+    pub fn is_changed<T>(&self) -> bool
+    where
+        T: Resource,
+    {
+        self.existing_entry(TypeId::of::<T>())
+            .map_or(false, |entry| entry.changed.load(Ordering::Acquire))
     }
 
-    /// Marks this resource as not mutably borrowed.
-    fn release_mutable(&self) {
-        debug_assert_eq!(self.0.load(Ordering::Acquire), u32::max_value());
-        self.0.store(0, Ordering::Release);
+    /// Finds the entry for a resource without constructing lazy resources.
+    fn existing_entry(&self, type_id: TypeId) -> Option<&ResourceEntry> {
+        self.types
+            .get(&type_id)
+            .or_else(|| self.lazy.get(&type_id).and_then(|lazy| lazy.entry.get()))
+            .or_else(|| self.shared_entry(type_id))
     }
 
-    /// Attempts to obtain an immutable borrow, returning `true` if successful
-    /// and `false` otherwise.
-    fn obtain_immutable(&self) -> bool {
-        loop {
-            let val = self.0.load(Ordering::Acquire);
+    /// Marks the resource `T` as cloneable, so that it is
+    /// included in `snapshot`s of this `Resources`.
+    pub fn register_clone<T>(&mut self)
+    where
+        T: Resource + Clone,
+    {
+        self.cloners.insert(TypeId::of::<T>(), clone_resource::<T>);
+    }
 
-            if val == u32::max_value() {
-                return false;
-            }
+    /// Inserts a new resource and marks it as cloneable. See `register_clone`.
+    ///
+    /// Replaces an existing value of the same type.
+    pub fn insert_cloneable<T>(&mut self, resource: T)
+    where
+        T: Resource + Clone,
+    {
+        self.register_clone::<T>();
+        self.insert(resource);
+    }
 
-            if self.0.compare_and_swap(val, val + 1, Ordering::AcqRel) == val {
-                return true;
+    /// Clones each resource registered with `register_clone` into
+    /// a new `Resources`. Other resources are skipped.
+    ///
+    /// The snapshot keeps the registrations, and can be restored
+    /// with `extend`.
+    ///
+    /// Returns an error if a cloneable resource is mutably borrowed.
+    pub fn snapshot(&self) -> Result<OwnedResources> {
+        let mut snapshot = OwnedResources::with_hasher(self.arcs.hasher().clone());
+        for (&type_id, &cloner) in &self.cloners {
+            if let Some(entry) = self.existing_entry(type_id) {
+                let value = entry.borrow_dyn()?;
+                snapshot
+                    .types
+                    .insert(type_id, ResourceEntry::new(cloner(&*value)));
             }
+            snapshot.cloners.insert(type_id, cloner);
         }
+        Ok(snapshot)
     }
 
-    /// Releases an immutable borrow.
-    fn release_immutable(&self) {
-        self.0.fetch_sub(1, Ordering::AcqRel);
+    /// Resets the changed state of all resources.
+    pub fn clear_changed(&self) {
+        let lazy = self.lazy.values().filter_map(|lazy| lazy.entry.get());
+        let shared = self.shared.lock().expect("shared resources poisoned");
+        let shared = shared.values().map(|entry| &**entry);
+        for entry in self.types.values().chain(lazy).chain(shared) {
+            entry.changed.store(false, Ordering::Release);
+        }
     }
-}
-
-pub struct Ref<'a, T> {
-    flag: &'a BorrowFlag,
-    value: &'a T,
-}
 
-impl<'a, T> Deref for Ref<'a, T> {
-    type Target = T;
+    /// Returns a read-only view of these resources which hands out
+    /// plain references without touching borrow flags.
+    ///
+    /// Since the view holds a mutable borrow, no resource
+    /// can be mutated while it exists.
+    pub fn freeze(&mut self) -> FrozenResources {
+        self.apply_shared();
+        FrozenResources { inner: self }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.value
+    /// Method chaining alias for `insert`.
+    pub fn with<T>(mut self, resource: T) -> Self
+    where
+        T: Resource,
+    {
+        self.insert(resource);
+        self
     }
-}
 
-impl<'a, T> Drop for Ref<'a, T> {
-    fn drop(&mut self) {
-        self.flag.release_immutable();
+    /// Method chaining alias for `insert_bundle`.
+    pub fn with_bundle(mut self, bundle: impl ResourceBundle) -> Self {
+        self.insert_bundle(bundle);
+        self
     }
 }
 
-pub struct RefMut<'a, T> {
-    flag: &'a BorrowFlag,
-    value: &'a mut T,
+/// A read-only view of `OwnedResources` returned by `OwnedResources::freeze`.
+///
+/// Borrows are not tracked, so accessing resources through
+/// this view is cheaper than through `ResourcesProvider`.
+pub struct FrozenResources<'a> {
+    inner: &'a mut OwnedResources,
 }
 
-impl<'a, T> Deref for RefMut<'a, T> {
-    type Target = T;
+impl<'a> FrozenResources<'a> {
+    /// Returns a reference to a resource.
+    ///
+    /// # Panics
+    /// Panics if the resource does not exist.
+    pub fn get<T>(&self) -> &T
+    where
+        T: Resource,
+    {
+        self.try_get().unwrap()
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.value
+    /// Returns a reference to a resource.
+    ///
+    /// Returns an error if the resource does not exist.
+    pub fn try_get<T>(&self) -> Result<&T>
+    where
+        T: Resource,
+    {
+        let entry = self.inner.entry::<T>()?;
+        // Safety: `inner` is borrowed mutably for the lifetime of this view,
+        // so no mutable borrow of the resource can exist.
+        Ok(Box::deref(unsafe { &*entry.value.get() })
+            .as_any()
+            .downcast_ref()
+            .expect("resource stored under the wrong type"))
     }
-}
 
-impl<'a, T> DerefMut for RefMut<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.value
+    /// Returns whether a resource of type `T` exists.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Resource,
+    {
+        self.inner.contains::<T>()
     }
 }
 
-impl<'a, T> Drop for RefMut<'a, T> {
+/// Restores the original resource replaced by `OwnedResources::scope`.
+struct ScopeGuard<'a> {
+    resources: &'a mut OwnedResources,
+    type_id: TypeId,
+    original: Option<ResourceEntry>,
+}
+
+impl<'a> Drop for ScopeGuard<'a> {
     fn drop(&mut self) {
-        self.flag.release_mutable();
+        match self.original.take() {
+            Some(original) => {
+                self.resources.types.insert(self.type_id, original);
+            }
+            None => {
+                self.resources.types.remove(&self.type_id);
+            }
+        }
     }
 }
 
-pub trait ResourcesProvider {
+impl ResourcesProvider for OwnedResources {
     /// Immutably borrows a resource from this container.
     ///
     /// # Panics
@@ -132,7 +1616,10 @@ pub trait ResourcesProvider {
     /// is already mutably borrowed.
     fn get<T>(&self) -> Ref<T>
     where
-        T: Resource;
+        T: Resource,
+    {
+        expect_borrow::<T, _>(self.try_get(), || self.borrowed_at::<T>())
+    }
 
     /// Immutably borrows a resource from this container.
     ///
@@ -140,7 +1627,10 @@ pub trait ResourcesProvider {
     /// or if it is already mutably borrowed.
     fn try_get<T>(&self) -> Result<Ref<T>>
     where
-        T: Resource;
+        T: Resource,
+    {
+        self.entry::<T>()?.borrow()
+    }
 
     /// Mutably borrows a resource from this container.
     ///
@@ -148,7 +1638,10 @@ pub trait ResourcesProvider {
     /// Panics of the resource does not exist or it is already borrowed.
     fn get_mut<T>(&self) -> RefMut<T>
     where
-        T: Resource;
+        T: Resource,
+    {
+        expect_borrow::<T, _>(self.try_get_mut(), || self.borrowed_at::<T>())
+    }
 
     /// Mutably borrows a resource from this container.
     ///
@@ -156,101 +1649,78 @@ pub trait ResourcesProvider {
     /// or it is already borrowed.
     fn try_get_mut<T>(&self) -> Result<RefMut<T>>
     where
-        T: Resource;
-
-    /// Converts this `ResourcesProvider` into a `ResourcesRef`
-    /// suitable for passing to dynamically-dispatched functions.
-    fn as_resources_ref(&self) -> ResourcesEnum;
-}
-
-pub enum ResourcesEnum<'a> {
-    Owned(&'a OwnedResources),
-    Ref(&'a RefResources<'a, OwnedResources>),
-    DoubleRef(&'a ResourcesEnum<'a>),
-}
+        T: Resource,
+    {
+        self.entry::<T>()?.borrow_mut()
+    }
 
-impl<'a> ResourcesProvider for ResourcesEnum<'a> {
-    fn get<T>(&self) -> Ref<T>
+    fn try_get_keyed<T>(&self, key: &str) -> Result<Ref<T>>
     where
         T: Resource,
     {
-        match self {
-            ResourcesEnum::Owned(res) => res.get(),
-            ResourcesEnum::Ref(res) => res.get(),
-            ResourcesEnum::DoubleRef(res) => res.get(),
-        }
+        self.keyed_entry::<T>(key)?.borrow()
     }
 
-    fn try_get<T>(&self) -> Result<Ref<T>>
+    fn try_get_keyed_mut<T>(&self, key: &str) -> Result<RefMut<T>>
     where
         T: Resource,
     {
-        match self {
-            ResourcesEnum::Owned(res) => res.try_get(),
-            ResourcesEnum::Ref(res) => res.try_get(),
-            ResourcesEnum::DoubleRef(res) => res.try_get(),
-        }
+        self.keyed_entry::<T>(key)?.borrow_mut()
     }
 
-    fn get_mut<T>(&self) -> RefMut<T>
+    fn try_get_by_id<T>(&self, id: ResourceId<T>) -> Result<Ref<T>>
     where
         T: Resource,
     {
-        match self {
-            ResourcesEnum::Owned(res) => res.get_mut(),
-            ResourcesEnum::Ref(res) => res.get_mut(),
-            ResourcesEnum::DoubleRef(res) => res.get_mut(),
-        }
+        self.entry_by_id(id)?.borrow()
     }
 
-    fn try_get_mut<T>(&self) -> Result<RefMut<T>>
+    fn try_get_mut_by_id<T>(&self, id: ResourceId<T>) -> Result<RefMut<T>>
     where
         T: Resource,
     {
-        match self {
-            ResourcesEnum::Owned(res) => res.try_get_mut(),
-            ResourcesEnum::Ref(res) => res.try_get_mut(),
-            ResourcesEnum::DoubleRef(res) => res.try_get_mut(),
-        }
+        self.entry_by_id(id)?.borrow_mut()
     }
 
     fn as_resources_ref(&self) -> ResourcesEnum {
-        ResourcesEnum::DoubleRef(self)
+        ResourcesEnum::Owned(self)
     }
 }
 
-/// Stores a set of owned values, each with a distinct type.
+/// Stores a set of owned values, each with a distinct type, which
+/// need not be `Send` or `Sync`.
 ///
-/// Resources are borrow checked at runtime.
-pub struct OwnedResources {
-    /// Mapping from resource types to their structs.
-    types: FxHashMap<TypeId, (BorrowFlag, UnsafeCell<Box<dyn Resource>>)>,
+/// This is the thread-local counterpart to `OwnedResources`, for values
+/// such as scripting VMs or window handles which never leave the thread
+/// that created them. It is passed to systems marked as thread-local
+/// by `Executor::execute_thread_local`.
+pub struct ThreadLocalResources {
+    types: HashMap<TypeId, (BorrowFlag, UnsafeCell<Box<dyn Any>>)>,
+    /// Opts out of `Send` and `Sync`.
+    _not_send: PhantomData<*const ()>,
 }
 
-// Safety: we ensure correct resource borrows through the atomic `BorrowFlag`.
-unsafe impl Send for OwnedResources {}
-unsafe impl Sync for OwnedResources {}
-
-impl Default for OwnedResources {
+impl Default for ThreadLocalResources {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl OwnedResources {
-    /// Creates a new `Resources` with no stored values.
+impl ThreadLocalResources {
+    /// Creates a new `ThreadLocalResources` with no stored values.
     pub fn new() -> Self {
         Self {
-            types: FxHashMap::with_hasher(FxBuildHasher::default()),
+            types: HashMap::default(),
+            _not_send: PhantomData,
         }
     }
 
-    /// Inserts a new resource into this `Resources`.
+    /// Inserts a new resource into this `ThreadLocalResources`.
     ///
     /// Replaces an existing value of the same type.
     pub fn insert<T>(&mut self, resource: T)
     where
-        T: Resource,
+        T: 'static,
     {
         self.types.insert(
             TypeId::of::<T>(),
@@ -261,105 +1731,125 @@ impl OwnedResources {
     /// Method chaining alias for `insert`.
     pub fn with<T>(mut self, resource: T) -> Self
     where
-        T: Resource,
+        T: 'static,
     {
         self.insert(resource);
         self
     }
-}
 
-impl ResourcesProvider for OwnedResources {
+    /// Removes a resource from this `ThreadLocalResources`, returning it.
+    ///
+    /// Returns `None` if the resource does not exist.
+    pub fn remove<T>(&mut self) -> Option<T>
+    where
+        T: 'static,
+    {
+        self.types.remove(&TypeId::of::<T>()).map(|(_, resource)| {
+            *resource
+                .into_inner()
+                .downcast::<T>()
+                .expect("resource stored under the wrong type")
+        })
+    }
+
+    /// Returns where the resource `T` was most recently borrowed, if it is
+    /// currently borrowed. Always `None` in release builds.
+    pub fn borrowed_at<T>(&self) -> Option<&'static Location<'static>>
+    where
+        T: 'static,
+    {
+        self.types
+            .get(&TypeId::of::<T>())
+            .and_then(|(flag, _)| flag.borrowed_at())
+    }
+
     /// Immutably borrows a resource from this container.
     ///
     /// # Panics
     /// Panics if the resource does not exist or if it
     /// is already mutably borrowed.
-    fn get<T>(&self) -> Ref<T>
+    #[track_caller]
+    pub fn get<T>(&self) -> Ref<T>
     where
-        T: Resource,
+        T: 'static,
     {
-        self.try_get().unwrap()
+        expect_borrow::<T, _>(self.try_get(), || self.borrowed_at::<T>())
     }
 
     /// Immutably borrows a resource from this container.
     ///
-    /// Returns `None` if the resource does not exist
+    /// Returns an error if the resource does not exist
     /// or if it is already mutably borrowed.
-    fn try_get<T>(&self) -> Result<Ref<T>>
+    #[track_caller]
+    pub fn try_get<T>(&self) -> Result<Ref<T>>
     where
-        T: Resource,
+        T: 'static,
     {
-        self.types
+        let (flag, resource) = self
+            .types
             .get(&TypeId::of::<T>())
-            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
-            .and_then(|(flag, resource)| {
-                if flag.obtain_immutable() {
-                    Ok(Ref {
-                        flag,
-                        value: Box::deref(unsafe { &*resource.get() })
-                            .as_any()
-                            .downcast_ref()
-                            .unwrap(),
-                    })
-                } else {
-                    Err(ResourceError::AlreadyBorrowed)
-                }
+            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))?;
+        if flag.obtain_immutable() {
+            Ok(Ref {
+                flag,
+                value: unsafe { &*resource.get() }.downcast_ref().unwrap(),
             })
+        } else {
+            Err(ResourceError::AlreadyBorrowed)
+        }
     }
 
     /// Mutably borrows a resource from this container.
     ///
     /// # Panics
     /// Panics of the resource does not exist or it is already borrowed.
-    fn get_mut<T>(&self) -> RefMut<T>
+    #[track_caller]
+    pub fn get_mut<T>(&self) -> RefMut<T>
     where
-        T: Resource,
+        T: 'static,
     {
-        self.try_get_mut().unwrap()
+        expect_borrow::<T, _>(self.try_get_mut(), || self.borrowed_at::<T>())
     }
 
     /// Mutably borrows a resource from this container.
     ///
-    /// Returns `None` if the resource does not exist
+    /// Returns an error if the resource does not exist
     /// or it is already borrowed.
-    fn try_get_mut<T>(&self) -> Result<RefMut<T>>
+    #[track_caller]
+    pub fn try_get_mut<T>(&self) -> Result<RefMut<T>>
     where
-        T: Resource,
+        T: 'static,
     {
-        self.types
+        let (flag, resource) = self
+            .types
             .get(&TypeId::of::<T>())
-            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
-            .and_then(|(flag, resource)| {
-                if flag.obtain_mutable() {
-                    Ok(RefMut {
-                        flag,
-                        value: Box::deref_mut(unsafe { &mut *resource.get() })
-                            .as_any_mut()
-                            .downcast_mut()
-                            .unwrap(),
-                    })
-                } else {
-                    Err(ResourceError::AlreadyBorrowed)
-                }
+            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))?;
+        if flag.obtain_mutable() {
+            Ok(RefMut {
+                flag,
+                value: unsafe { &mut *resource.get() }.downcast_mut().unwrap(),
             })
-    }
-
-    fn as_resources_ref(&self) -> ResourcesEnum {
-        ResourcesEnum::Owned(self)
+        } else {
+            Err(ResourceError::AlreadyBorrowed)
+        }
     }
 }
 
 type RefEntry = (BorrowFlag, UnsafeCell<*mut dyn Resource>);
 
+/// Temporary borrows stored by `RefResources`. Spills onto
+/// the heap when more than 4 are inserted.
+type RefVec = SmallVec<[(TypeId, RefEntry); 4]>;
+
 pub unsafe trait ResourceTuple<'a> {
-    fn into_vec(self) -> ArrayVec<[(TypeId, RefEntry); 4]>;
+    fn into_vec(self) -> RefVec;
 }
 
 macro_rules! impl_resource_tuple {
     ($($ty:ident, $idx:tt),*) => {
         unsafe impl <'a, $($ty,)*> ResourceTuple<'a> for ($(&'a mut $ty,)*) where $($ty: Resource,)* {
-            fn into_vec(self) -> ArrayVec<[(TypeId, RefEntry); 4]> {
-                let mut vec = ArrayVec::new();
+            fn into_vec(self) -> RefVec {
+                let mut vec = SmallVec::new();
 
                 $(
                     vec.push((TypeId::of::<$ty>(), (BorrowFlag::default(), UnsafeCell::new(self.$idx as *mut _))));
@@ -375,15 +1865,41 @@ impl_resource_tuple!(A, 0);
 impl_resource_tuple!(A, 0, B, 1);
 impl_resource_tuple!(A, 0, B, 1, C, 2);
 impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10);
+impl_resource_tuple!(A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10, L, 11);
+impl_resource_tuple!(
+    A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10, L, 11, M, 12
+);
+impl_resource_tuple!(
+    A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10, L, 11, M, 12, N, 13
+);
+impl_resource_tuple!(
+    A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10, L, 11, M, 12, N, 13, O, 14
+);
+impl_resource_tuple!(
+    A, 0, B, 1, C, 2, D, 3, E, 4, F, 5, G, 6, H, 7, I, 8, J, 9, K, 10, L, 11, M, 12, N, 13, O, 14,
+    P, 15
+);
 
 /// A wrapper over `OwnedResources` which allows insertion of temporary
 /// borrows.
 pub struct RefResources<'a, R> {
     inner: &'a R,
-    refs: ArrayVec<[(TypeId, RefEntry); 4]>,
+    refs: RefVec,
     _lifetime: PhantomData<&'a mut dyn Resource>,
 }
 
+// Safety: borrows of the temporary resources are tracked through
+// the atomic `BorrowFlag`, and the borrowed values are `Send + Sync`.
+unsafe impl<'a, R> Send for RefResources<'a, R> where R: Sync {}
+unsafe impl<'a, R> Sync for RefResources<'a, R> where R: Sync {}
+
 impl<'a, R> RefResources<'a, R> {
     /// Creates a new `RefResources` wrapping the given resources.
     pub fn new(inner: &'a R, refs: impl ResourceTuple<'a>) -> Self {
@@ -395,64 +1911,100 @@ impl<'a, R> RefResources<'a, R> {
     }
 }
 
+impl<'b> RefResources<'b, OwnedResources> {
+    /// Returns where the resource `T` was most recently borrowed, if it is
+    /// currently borrowed. Always `None` in release builds.
+    pub fn borrowed_at<T>(&self) -> Option<&'static Location<'static>>
+    where
+        T: Resource,
+    {
+        match self.refs.iter().find(|(id, _)| *id == TypeId::of::<T>()) {
+            Some((_, (flag, _))) => flag.borrowed_at(),
+            None => self.inner.borrowed_at::<T>(),
+        }
+    }
+}
+
 impl<'b> ResourcesProvider for RefResources<'b, OwnedResources> {
     fn get<T>(&self) -> Ref<T>
     where
         T: Resource,
     {
-        self.try_get::<T>().unwrap()
+        expect_borrow::<T, _>(self.try_get(), || self.borrowed_at::<T>())
     }
 
     fn try_get<T>(&self) -> Result<Ref<T>>
     where
         T: Resource,
     {
-        self.refs
-            .iter()
-            .find(|(id, _)| *id == TypeId::of::<T>())
-            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
-            .and_then(|(_, (flag, cell))| {
-                if flag.obtain_immutable() {
-                    Ok(Ref {
-                        flag,
-                        value: unsafe { &**cell.get() }.as_any().downcast_ref().unwrap(),
-                    })
-                } else {
-                    Err(ResourceError::AlreadyBorrowed)
-                }
-            })
-            .or_else(|_| self.inner.try_get())
+        match self.refs.iter().find(|(id, _)| *id == TypeId::of::<T>()) {
+            Some((_, (flag, cell))) if flag.obtain_immutable() => Ok(Ref {
+                flag,
+                value: unsafe { &**cell.get() }.as_any().downcast_ref().unwrap(),
+            }),
+            Some(_) => Err(ResourceError::AlreadyBorrowed),
+            None => self.inner.try_get(),
+        }
     }
 
     fn get_mut<T>(&self) -> RefMut<T>
     where
         T: Resource,
     {
-        self.try_get_mut().unwrap()
+        expect_borrow::<T, _>(self.try_get_mut(), || self.borrowed_at::<T>())
     }
 
     fn try_get_mut<T>(&self) -> Result<RefMut<T>>
     where
         T: Resource,
     {
-        self.refs
-            .iter()
-            .find(|(id, _)| *id == TypeId::of::<T>())
-            .ok_or_else(|| ResourceError::NotFound(std::any::type_name::<T>()))
-            .and_then(|(_, (flag, cell))| {
-                if flag.obtain_mutable() {
-                    Ok(RefMut {
-                        flag,
-                        value: unsafe { &mut **cell.get() }
-                            .as_any_mut()
-                            .downcast_mut()
-                            .unwrap(),
-                    })
-                } else {
-                    Err(ResourceError::AlreadyBorrowed)
-                }
-            })
-            .or_else(|_| self.inner.try_get_mut())
+        match self.refs.iter().find(|(id, _)| *id == TypeId::of::<T>()) {
+            Some((_, (flag, cell))) if flag.obtain_mutable() => Ok(RefMut {
+                flag,
+                value: unsafe { &mut **cell.get() }
+                    .as_any_mut()
+                    .downcast_mut()
+                    .unwrap(),
+            }),
+            Some(_) => Err(ResourceError::AlreadyBorrowed),
+            None => self.inner.try_get_mut(),
+        }
+    }
+
+    fn try_get_keyed<T>(&self, key: &str) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        self.inner.try_get_keyed(key)
+    }
+
+    fn try_get_keyed_mut<T>(&self, key: &str) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        self.inner.try_get_keyed_mut(key)
+    }
+
+    fn try_get_by_id<T>(&self, id: ResourceId<T>) -> Result<Ref<T>>
+    where
+        T: Resource,
+    {
+        if self.refs.iter().any(|(ty, _)| *ty == TypeId::of::<T>()) {
+            self.try_get()
+        } else {
+            self.inner.try_get_by_id(id)
+        }
+    }
+
+    fn try_get_mut_by_id<T>(&self, id: ResourceId<T>) -> Result<RefMut<T>>
+    where
+        T: Resource,
+    {
+        if self.refs.iter().any(|(ty, _)| *ty == TypeId::of::<T>()) {
+            self.try_get_mut()
+        } else {
+            self.inner.try_get_mut_by_id(id)
+        }
     }
 
     fn as_resources_ref(&self) -> ResourcesEnum {
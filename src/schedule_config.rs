@@ -0,0 +1,49 @@
+//! Configuration of an `Executor`'s systems from data files.
+
+use crate::Stage;
+use std::collections::BTreeMap;
+
+/// Overrides for an `Executor`'s systems by name,
+/// applied with `Executor::apply_config`.
+///
+/// With the `serialize` feature, this can be loaded from
+/// data files such as RON or TOML:
+///
+/// ```toml
+/// [systems.mob_ai]
+/// enabled = false
+///
+/// [systems.physics]
+/// stage = "PostUpdate"
+/// after = ["input"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct ScheduleConfig {
+    /// Overrides keyed by system name.
+    pub systems: BTreeMap<String, SystemConfig>,
+}
+
+/// Overrides for the systems with a given name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SystemConfig {
+    /// Enables or disables the system.
+    pub enabled: Option<bool>,
+    /// Moves the system to another stage.
+    pub stage: Option<Stage>,
+    /// Names or labels of systems which the system must run before,
+    /// in addition to its own ordering constraints.
+    pub before: Vec<String>,
+    /// Names or labels of systems which the system must run after,
+    /// in addition to its own ordering constraints.
+    pub after: Vec<String>,
+}
@@ -1,22 +1,502 @@
+use crate::async_system::{AsyncSystem, Task, TaskPool};
+#[cfg(feature = "dylib")]
+use crate::dylib::{LibraryError, LibraryId, LoadedLibrary};
 use crate::resources::ResourcesEnum;
-use crate::{OwnedResources, ResourcesProvider, World};
+use crate::schedule_config::ScheduleConfig;
+use crate::{
+    CommandBuffer, EventHandlers, OwnedResources, ResourcesProvider, ThreadLocalResources, Tick,
+    Time, World,
+};
+use once_cell::sync::OnceCell;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::any::TypeId;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+#[cfg(feature = "dylib")]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// Number of executors executing on the current thread; see `Executor::run_tick`.
+    static EXECUTION_DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/// An error returned by a fallible system.
+pub type SystemError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Logs an error returned by the given system.
+///
+/// Used by systems created with the `system` macro
+/// when they are run through `run` rather than `try_run`.
+#[doc(hidden)]
+pub fn log_system_error(system: &str, error: &SystemError) {
+    log::error!("system {} failed: {}", system, error);
+}
+
+/// What the `Executor` does when a system returns an error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Logs the error and continues with the next system.
+    Log,
+    /// Logs the error and skips the remaining systems for this tick.
+    AbortTick,
+    /// Panics with the error.
+    Panic,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::Log
+    }
+}
+
+/// How a system accesses the `World`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WorldAccess {
+    /// The system does not access the world.
+    Unused,
+    /// The system only needs `&World`.
+    Read,
+    /// The system needs `&mut World`.
+    Write,
+}
+
+impl Default for WorldAccess {
+    fn default() -> Self {
+        WorldAccess::Unused
+    }
+}
+
+/// The resources and world access declared by a system,
+/// used to determine which systems can run in parallel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemAccess {
+    /// Resource types which are only borrowed immutably.
+    pub resource_reads: Vec<TypeId>,
+    /// Resource types which are borrowed mutably.
+    pub resource_writes: Vec<TypeId>,
+    /// Resource types among the reads and writes which the
+    /// system can run without, e.g. `Option<&T>` parameters.
+    pub optional_resources: Vec<TypeId>,
+    pub world: WorldAccess,
+}
+
+impl SystemAccess {
+    /// Returns whether two systems with these accesses cannot run at the same time.
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        let world_conflict = match (self.world, other.world) {
+            (WorldAccess::Unused, _) | (_, WorldAccess::Unused) => false,
+            (WorldAccess::Read, WorldAccess::Read) => false,
+            _ => true,
+        };
+
+        world_conflict
+            || self
+                .resource_writes
+                .iter()
+                .any(|ty| other.resource_reads.contains(ty) || other.resource_writes.contains(ty))
+            || other
+                .resource_writes
+                .iter()
+                .any(|ty| self.resource_reads.contains(ty))
+    }
+}
 
 #[doc(hidden)]
 pub trait RawSystem: Send + Sync + 'static {
     /// Runs the system with the given resources and world.
     fn run(&self, resources: &ResourcesEnum, world: &mut World, executor: &Executor);
 
+    /// Runs the system, returning an error if it failed.
+    ///
+    /// The executor calls this rather than `run`. Systems created
+    /// with the `system` macro implement it when their function
+    /// returns a `Result`.
+    fn try_run(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.run(resources, world, executor);
+        Ok(())
+    }
+
     /// Set up the system with the given resources and world.
     fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World);
+
+    /// Returns the name of this system, used by `Executor::set_enabled`.
+    ///
+    /// Systems created with the `system` macro are named after their
+    /// function, qualified by its module path, e.g. `game::physics::update`.
+    /// Methods looking up systems by name also accept a part of
+    /// the name following `::`, e.g. `update` or `physics::update`.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Returns the resources and world access of this system, or `None`
+    /// if it is unknown, in which case the system never runs in parallel.
+    fn access(&self) -> Option<SystemAccess> {
+        None
+    }
+
+    /// Returns whether this system needs exclusive access to the world,
+    /// i.e. whether it must run through `run` rather than `run_shared`.
+    ///
+    /// Systems with unknown access, thread-local systems and systems
+    /// which don't implement `run_shared` are exclusive. The `system`
+    /// macro infers this from whether the function takes `&mut World`
+    /// or `&World`.
+    fn is_exclusive(&self) -> bool {
+        self.is_thread_local()
+            || !self.supports_shared()
+            || self
+                .access()
+                .map_or(true, |access| access.world == WorldAccess::Write)
+    }
+
+    /// Returns whether this system implements `run_shared`
+    /// and `try_run_shared`.
+    fn supports_shared(&self) -> bool {
+        false
+    }
+
+    /// Runs the system with shared access to the world.
+    ///
+    /// Only called if `supports_shared` returns `true` and `access`
+    /// declares `WorldAccess::Read` or `WorldAccess::Unused`.
+    /// Does nothing by default.
+    fn run_shared(&self, _resources: &ResourcesEnum, _world: &World, _executor: &Executor) {}
+
+    /// Runs the system with shared access to the world,
+    /// returning an error if it failed.
+    fn try_run_shared(
+        &self,
+        resources: &ResourcesEnum,
+        world: &World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.run_shared(resources, world, executor);
+        Ok(())
+    }
+
+    /// Returns the labels and ordering constraints of this system.
+    fn ordering(&self) -> SystemOrdering {
+        SystemOrdering::default()
+    }
+
+    /// Returns the stage this system runs in when added without
+    /// an explicit stage, e.g. with `Executor::add`.
+    fn stage(&self) -> Stage {
+        Stage::default()
+    }
+
+    /// Returns whether this system needs access to `ThreadLocalResources`.
+    ///
+    /// Thread-local systems are run through `run_thread_local` and
+    /// can only be executed by `Executor::execute_thread_local`.
+    fn is_thread_local(&self) -> bool {
+        false
+    }
+
+    /// Runs the system with the given resources, thread-local resources, and world.
+    fn run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        _thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) {
+        self.run(resources, world, executor);
+    }
+
+    /// Runs the system with the given resources, thread-local resources,
+    /// and world, returning an error if it failed.
+    ///
+    /// Systems which aren't thread-local are run through `try_run`.
+    fn try_run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        if !self.is_thread_local() {
+            return self.try_run(resources, world, executor);
+        }
+        self.run_thread_local(resources, thread_local, world, executor);
+        Ok(())
+    }
+}
+
+/// Labels and ordering constraints of a system, resolved by
+/// `Executor::set_up` into an order within each stage.
+///
+/// Constraints naming labels which no system in the
+/// same stage carries are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemOrdering {
+    pub labels: Vec<&'static str>,
+    /// Labels of systems which this system must run before.
+    pub before: Vec<&'static str>,
+    /// Labels of systems which this system must run after.
+    pub after: Vec<&'static str>,
+}
+
+impl SystemOrdering {
+    /// Returns whether the constraints of either system
+    /// require this system to run before `other`.
+    fn precedes(&self, other: &SystemOrdering) -> bool {
+        self.before.iter().any(|label| other.labels.contains(label))
+            || other.after.iter().any(|label| self.labels.contains(label))
+    }
+}
+
+/// A stage of a tick. The `Executor` runs all systems
+/// in one stage before moving on to the next.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stage {
+    First,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Last,
+}
+
+impl Default for Stage {
+    fn default() -> Self {
+        Stage::Update
+    }
+}
+
+/// All stages, in execution order.
+const STAGES: [Stage; 5] = [
+    Stage::First,
+    Stage::PreUpdate,
+    Stage::Update,
+    Stage::PostUpdate,
+    Stage::Last,
+];
+
+/// A condition evaluated each tick to determine whether a system runs.
+type RunCriteria = Arc<dyn Fn(&ResourcesEnum) -> bool + Send + Sync>;
+
+/// A system registered with an `Executor`.
+struct SystemEntry {
+    system: Box<dyn RawSystem>,
+    stage: Stage,
+    enabled: AtomicBool,
+    run_criteria: Option<RunCriteria>,
+    timings: Mutex<SystemTimings>,
+    /// Whether the system is skipped once the tick exceeds its time budget.
+    low_priority: bool,
+    /// Number of times the system was skipped due to the time budget.
+    budget_skips: AtomicU64,
+    /// Number of consecutive runs which exceeded the watchdog threshold.
+    slow_runs: AtomicU32,
+    /// Additional ordering constraints set by `Executor::apply_config`.
+    config_before: Vec<String>,
+    config_after: Vec<String>,
+    /// Whether the system may run concurrently with
+    /// adjacent independent systems in `execute_chunked`.
+    independent: bool,
+    /// The library which registered the system, if any.
+    #[cfg(feature = "dylib")]
+    library: Option<LibraryId>,
+}
+
+impl SystemEntry {
+    /// Returns whether the system has the given name or label,
+    /// where `ordering` is the system's ordering.
+    fn matches(&self, label: &str, ordering: &SystemOrdering) -> bool {
+        self.has_name(label) || ordering.labels.contains(&label)
+    }
+
+    /// Returns whether the system has the given name: either its full
+    /// name or a part of it following `::`, e.g. `update` or
+    /// `physics::update` for a system named `game::physics::update`.
+    fn has_name(&self, name: &str) -> bool {
+        let full = self.system.name();
+        full == name
+            || full
+                .strip_suffix(name)
+                .map_or(false, |prefix| prefix.ends_with("::"))
+    }
+
+    /// Returns whether the system is enabled and its run criteria, if any, are met.
+    fn should_run(&self, resources: &ResourcesEnum) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+            && self
+                .run_criteria
+                .as_ref()
+                .map_or(true, |criteria| criteria(resources))
+    }
+}
+
+/// An async system registered with an `Executor`.
+struct AsyncEntry {
+    system: Box<dyn AsyncSystem>,
+    /// The stage at whose beginning the system is started.
+    stage: Stage,
+    /// The stage at whose end the system is joined.
+    sync: Stage,
+    /// The system's running task, if it has not been joined yet.
+    task: Mutex<Option<Task<CommandBuffer>>>,
+}
+
+impl AsyncEntry {
+    /// Waits for the running task, if any, and applies its commands.
+    fn join(&self, world: &mut World) {
+        let task = self.task.lock().expect("async task poisoned").take();
+        if let Some(task) = task {
+            task.join().write(world);
+        }
+    }
+}
+
+/// Metadata about a system registered with an `Executor`,
+/// as returned by `Executor::systems`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SystemInfo {
+    /// Position of the system in execution order.
+    pub index: usize,
+    pub name: &'static str,
+    pub enabled: bool,
+    pub stage: Stage,
+    /// Whether the system needs exclusive access to the world;
+    /// see `RawSystem::is_exclusive`.
+    pub exclusive: bool,
+    /// Whether the system is skipped once the tick exceeds its time budget.
+    pub low_priority: bool,
+    /// Number of times the system was skipped due to the time budget.
+    pub budget_skips: u64,
+}
+
+/// Reports systems which run longer than a threshold;
+/// see `Executor::set_watchdog`.
+#[derive(Clone)]
+pub struct Watchdog {
+    threshold: Duration,
+    disable_after: Option<u32>,
+    on_slow: Option<Arc<dyn Fn(&'static str, Duration) + Send + Sync>>,
+}
+
+impl Watchdog {
+    /// Creates a watchdog reporting runs longer than `threshold`.
+    ///
+    /// By default, slow runs are logged as warnings.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            disable_after: None,
+            on_slow: None,
+        }
+    }
+
+    /// Disables systems after the given number of consecutive slow runs.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn disable_after(mut self, runs: u32) -> Self {
+        self.disable_after = Some(runs);
+        self
+    }
+
+    /// Calls `f` with the name and duration of each slow run instead of logging it.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn on_slow(mut self, f: impl Fn(&'static str, Duration) + Send + Sync + 'static) -> Self {
+        self.on_slow = Some(Arc::new(f));
+        self
+    }
+}
+
+/// Wall-clock time spent running a system, recorded
+/// when profiling is enabled with `Executor::set_profiling`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemTimings {
+    /// Duration of the most recent run.
+    pub last: Duration,
+    /// Exponential moving average of the run duration,
+    /// weighting the most recent run by 1/16.
+    pub average: Duration,
+    /// Longest run duration.
+    pub max: Duration,
+    /// Number of runs recorded.
+    pub runs: u64,
+}
+
+impl SystemTimings {
+    fn record(&mut self, elapsed: Duration) {
+        self.average = if self.runs == 0 {
+            elapsed
+        } else {
+            (self.average * 15 + elapsed) / 16
+        };
+        self.last = elapsed;
+        self.max = self.max.max(elapsed);
+        self.runs += 1;
+    }
 }
 
 pub struct Executor {
-    systems: Vec<Box<dyn RawSystem>>,
+    /// Registered systems, sorted by stage.
+    systems: Vec<SystemEntry>,
+    /// Whether to record `SystemTimings`.
+    profiling: AtomicBool,
+    error_policy: ErrorPolicy,
+    async_systems: Vec<AsyncEntry>,
+    /// Runs the futures of async systems, created when first needed.
+    task_pool: OnceCell<TaskPool>,
+    watchdog: Option<Watchdog>,
+    /// Handlers for events queued with `World::queue_event`.
+    event_handlers: Option<EventHandlers>,
+    /// Stages at whose end queued events are dispatched.
+    event_sync_points: Vec<Stage>,
+    /// Cycles in the ordering constraints found by the last sort.
+    ordering_errors: Vec<ScheduleError>,
+    /// Whether a warning about dropped unhandled events was logged.
+    warned_unhandled_events: AtomicBool,
+    /// Whether parallel execution applies deferred changes in system order.
+    deterministic: bool,
+    /// Time after which low-priority systems are skipped for the rest of a tick.
+    time_budget: Option<Duration>,
+    /// Whether to advance the `Tick` and `Time` resources each tick.
+    manage_time: bool,
+    /// When the previous tick started, or `None` before the first tick.
+    last_tick: Mutex<Option<Instant>>,
+    /// Loaded system libraries. Declared after `systems`
+    /// so that they are unloaded after their systems are dropped.
+    #[cfg(feature = "dylib")]
+    libraries: Vec<LoadedLibrary>,
 }
 
 impl Default for Executor {
     fn default() -> Self {
-        Self { systems: vec![] }
+        Self {
+            systems: vec![],
+            profiling: AtomicBool::new(false),
+            error_policy: ErrorPolicy::default(),
+            async_systems: vec![],
+            task_pool: OnceCell::new(),
+            watchdog: None,
+            event_handlers: None,
+            event_sync_points: STAGES.to_vec(),
+            ordering_errors: vec![],
+            warned_unhandled_events: AtomicBool::new(false),
+            deterministic: false,
+            time_budget: None,
+            manage_time: false,
+            last_tick: Mutex::new(None),
+            #[cfg(feature = "dylib")]
+            libraries: vec![],
+        }
     }
 }
 
@@ -32,7 +512,52 @@ impl Executor {
 
     /// Adds the given system to the exectuor.
     pub fn add_boxed(&mut self, system: Box<dyn RawSystem>) {
-        self.systems.push(system);
+        self.add_boxed_to_stage(system.stage(), system);
+    }
+
+    /// Adds the given system to the given stage. Systems added with
+    /// `add` run in the stage given by `RawSystem::stage`,
+    /// which is `Stage::Update` unless declared otherwise.
+    ///
+    /// Within a stage, systems run in the order they were added,
+    /// until they are sorted by their ordering constraints
+    /// in `set_up`, `apply_config`, or `load_library`.
+    pub fn add_to_stage(&mut self, stage: Stage, system: impl RawSystem) {
+        self.add_boxed_to_stage(stage, Box::new(system));
+    }
+
+    /// Adds the given system to the given stage.
+    pub fn add_boxed_to_stage(&mut self, stage: Stage, system: Box<dyn RawSystem>) {
+        self.insert_entry(stage, system);
+    }
+
+    /// Inserts a system after the last system in the given stage,
+    /// returning its index.
+    fn insert_entry(&mut self, stage: Stage, system: Box<dyn RawSystem>) -> usize {
+        let index = self
+            .systems
+            .iter()
+            .position(|entry| entry.stage > stage)
+            .unwrap_or_else(|| self.systems.len());
+        self.systems.insert(
+            index,
+            SystemEntry {
+                system,
+                stage,
+                enabled: AtomicBool::new(true),
+                run_criteria: None,
+                timings: Mutex::new(SystemTimings::default()),
+                low_priority: false,
+                budget_skips: AtomicU64::new(0),
+                slow_runs: AtomicU32::new(0),
+                config_before: vec![],
+                config_after: vec![],
+                independent: false,
+                #[cfg(feature = "dylib")]
+                library: None,
+            },
+        );
+        index
     }
 
     /// Adds the given system to the executor.
@@ -43,27 +568,1515 @@ impl Executor {
         self
     }
 
+    /// Adds the given system to the given stage.
+    ///
+    /// Returns `Self` such that method calls for `Executor` can be chained.
+    pub fn with_stage(mut self, stage: Stage, system: impl RawSystem) -> Self {
+        self.add_to_stage(stage, system);
+        self
+    }
+
+    /// Sets what happens when a system returns an error.
+    /// Defaults to `ErrorPolicy::Log`.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// Sets what happens when a system returns an error.
+    ///
+    /// Returns `Self` such that method calls for `Executor` can be chained.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.set_error_policy(policy);
+        self
+    }
+
+    /// Adds an async system, which is started at the beginning of `stage`
+    /// and joined at the end of `sync`, blocking until its future completes.
+    ///
+    /// If `sync` comes before `stage`, the system is joined during the next tick,
+    /// so that its future can run across the end of the tick.
+    pub fn add_async(&mut self, stage: Stage, sync: Stage, system: impl AsyncSystem) {
+        self.async_systems.push(AsyncEntry {
+            system: Box::new(system),
+            stage,
+            sync,
+            task: Mutex::new(None),
+        });
+    }
+
+    /// Adds an async system, which is started at the beginning
+    /// of `stage` and joined at the end of `sync`.
+    ///
+    /// Returns `Self` such that method calls for `Executor` can be chained.
+    pub fn with_async(mut self, stage: Stage, sync: Stage, system: impl AsyncSystem) -> Self {
+        self.add_async(stage, sync, system);
+        self
+    }
+
+    /// Adds the given systems as a `SystemGroup` named `name`,
+    /// which can be enabled, ordered, and profiled as a unit.
+    pub fn add_group(
+        &mut self,
+        name: &'static str,
+        systems: impl IntoIterator<Item = Box<dyn RawSystem>>,
+    ) {
+        let mut group = SystemGroup::new(name);
+        for system in systems {
+            group.add_boxed(system);
+        }
+        self.add(group);
+    }
+
+    /// Sets whether the executor maintains the `Tick` and `Time` resources.
+    /// Disabled by default.
+    ///
+    /// When enabled, `set_up` inserts the resources if they are missing, and
+    /// each execution increments `Tick` and advances `Time` by the wall-clock
+    /// time since the previous execution. This should only be enabled for
+    /// the top-level executor of an application, not for executors
+    /// nested in systems or when time is advanced manually.
+    pub fn set_manage_time(&mut self, manage_time: bool) {
+        self.manage_time = manage_time;
+    }
+
+    /// Sets whether the executor maintains the `Tick` and `Time` resources.
+    ///
+    /// Returns `Self` such that method calls for `Executor` can be chained.
+    pub fn with_manage_time(mut self, manage_time: bool) -> Self {
+        self.set_manage_time(manage_time);
+        self
+    }
+
     /// Returns the number of system registrede for this executor.
     pub fn num_systems(&self) -> usize {
         self.systems.len()
     }
 
+    /// Adds the given system, which only runs on ticks
+    /// where `criteria` returns `true`.
+    ///
+    /// Other systems of the same name are not affected.
+    pub fn add_with_criteria(
+        &mut self,
+        system: impl RawSystem,
+        criteria: impl Fn(&ResourcesEnum) -> bool + Send + Sync + 'static,
+    ) {
+        let system: Box<dyn RawSystem> = Box::new(system);
+        let index = self.insert_entry(system.stage(), system);
+        self.systems[index].run_criteria = Some(Arc::new(criteria));
+    }
+
+    /// Sets the run criteria of all systems with the given name,
+    /// so that they only run on ticks where `criteria` returns `true`.
+    ///
+    /// Returns whether any system has the given name.
+    pub fn set_run_criteria(
+        &mut self,
+        name: &str,
+        criteria: impl Fn(&ResourcesEnum) -> bool + Send + Sync + 'static,
+    ) -> bool {
+        let criteria: RunCriteria = Arc::new(criteria);
+        let mut found = false;
+        for entry in self.systems.iter_mut().filter(|entry| entry.has_name(name)) {
+            entry.run_criteria = Some(Arc::clone(&criteria));
+            found = true;
+        }
+        found
+    }
+
+    /// Marks all systems with the given name as independent, so that
+    /// `execute_chunked` may run them concurrently with adjacent
+    /// independent systems.
+    ///
+    /// Returns whether any system has the given name.
+    pub fn set_independent(&mut self, name: &str, independent: bool) -> bool {
+        let mut found = false;
+        for entry in self.systems.iter_mut().filter(|entry| entry.has_name(name)) {
+            entry.independent = independent;
+            found = true;
+        }
+        found
+    }
+
+    /// Sets the watchdog which reports systems running longer than its threshold,
+    /// or removes it if `None`.
+    pub fn set_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Sets the event handlers to which the executor dispatches
+    /// events queued with `World::queue_event` at its event sync points.
+    ///
+    /// The handlers are set up by `set_up`. An executor without
+    /// handlers which is not executed by a system of another executor
+    /// drops the events still queued at the end of each tick, since
+    /// nothing would dispatch them, and logs a warning the first time.
+    pub fn set_event_handlers(&mut self, handlers: EventHandlers) {
+        self.event_handlers = Some(handlers);
+    }
+
+    /// Sets the event handlers to which the executor dispatches queued events.
+    ///
+    /// Returns `Self` such that method calls for `Executor` can be chained.
+    pub fn with_event_handlers(mut self, handlers: EventHandlers) -> Self {
+        self.set_event_handlers(handlers);
+        self
+    }
+
+    /// Sets the stages at whose end queued events are dispatched.
+    /// Defaults to every stage.
+    pub fn set_event_sync_points(&mut self, stages: &[Stage]) {
+        self.event_sync_points = stages.to_vec();
+    }
+
+    /// Sets whether `execute_parallel` and `execute_chunked` produce the
+    /// same observable results as executing the systems in series.
+    ///
+    /// Batches are formed deterministically and never contain conflicting
+    /// systems, so the only remaining source of nondeterminism is the order
+    /// in which systems of a batch finish. When enabled, commands and deferred
+    /// despawns submitted within a batch are applied in system order instead.
+    /// Changes submitted from threads spawned by a system are not ordered.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Sets the time budget of each tick. Once a tick has taken longer
+    /// than the budget, its remaining low-priority systems are skipped.
+    ///
+    /// Skips are counted in `SystemInfo::budget_skips`.
+    pub fn set_time_budget(&mut self, budget: Option<Duration>) {
+        self.time_budget = budget;
+    }
+
+    /// Marks all systems with the given name as low-priority, so that
+    /// they are skipped when a tick exceeds its time budget.
+    ///
+    /// Returns whether any system has the given name.
+    pub fn set_low_priority(&mut self, name: &str, low_priority: bool) -> bool {
+        let mut found = false;
+        for entry in self.systems.iter_mut().filter(|entry| entry.has_name(name)) {
+            entry.low_priority = low_priority;
+            found = true;
+        }
+        found
+    }
+
+    /// Enables or disables all systems with the given name.
+    /// Disabled systems are skipped when executing.
+    ///
+    /// Returns whether any system has the given name.
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for entry in self.systems.iter().filter(|entry| entry.has_name(name)) {
+            entry.enabled.store(enabled, Ordering::Relaxed);
+            found = true;
+        }
+        found
+    }
+
+    /// Returns whether the system with the given name is enabled,
+    /// or `None` if there is no such system.
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.systems
+            .iter()
+            .find(|entry| entry.has_name(name))
+            .map(|entry| entry.enabled.load(Ordering::Relaxed))
+    }
+
+    /// Removes the first system with the given name or label,
+    /// returning it if found.
+    ///
+    /// The remaining systems keep their order.
+    pub fn remove(&mut self, label: &str) -> Option<Box<dyn RawSystem>> {
+        let index = self.position(label)?;
+        Some(self.systems.remove(index).system)
+    }
+
+    /// Replaces the first system with the given name or label,
+    /// returning the old system if found.
+    ///
+    /// The new system takes the old system's place in the execution order,
+    /// and keeps its stage, run criteria, and enabled state. It is not set
+    /// up by the executor, so `RawSystem::set_up` should be called on it
+    /// beforehand if needed. If no system matches, `system` is dropped.
+    pub fn replace(&mut self, label: &str, system: impl RawSystem) -> Option<Box<dyn RawSystem>> {
+        let index = self.position(label)?;
+        let entry = &mut self.systems[index];
+        *entry.timings.get_mut().expect("system timings poisoned") = SystemTimings::default();
+        Some(std::mem::replace(&mut entry.system, Box::new(system)))
+    }
+
+    /// Returns the index of the first system with the given name or label.
+    fn position(&self, label: &str) -> Option<usize> {
+        self.systems
+            .iter()
+            .position(|entry| entry.matches(label, &entry.system.ordering()))
+    }
+
+    /// Enables or disables recording of the time spent in each system.
+    pub fn set_profiling(&self, enabled: bool) {
+        self.profiling.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns metadata about each system, in execution order.
+    ///
+    /// The order is final once `set_up` has been called.
+    pub fn systems(&self) -> impl Iterator<Item = SystemInfo> + '_ {
+        self.systems
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| SystemInfo {
+                index,
+                name: entry.system.name(),
+                enabled: entry.enabled.load(Ordering::Relaxed),
+                stage: entry.stage,
+                exclusive: entry.system.is_exclusive(),
+                low_priority: entry.low_priority,
+                budget_skips: entry.budget_skips.load(Ordering::Relaxed),
+            })
+    }
+
+    /// Checks that every resource declared by a system exists in `resources`,
+    /// and that no two adjacent independent systems (see `set_independent`)
+    /// have conflicting or unknown accesses.
+    ///
+    /// Returns every problem found, including cycles in the ordering
+    /// constraints. This should be called after `set_up`,
+    /// which may insert resources and reorders systems.
+    pub fn validate(&self, resources: &OwnedResources) -> Result<(), Vec<ScheduleError>> {
+        let mut errors = self.ordering_errors.clone();
+
+        for entry in &self.systems {
+            let access = match entry.system.access() {
+                Some(access) => access,
+                None => continue,
+            };
+            for &type_id in access.resource_reads.iter().chain(&access.resource_writes) {
+                if !access.optional_resources.contains(&type_id) && !resources.contains_id(type_id)
+                {
+                    errors.push(ScheduleError::MissingResource {
+                        system: entry.system.name(),
+                        type_id,
+                    });
+                }
+            }
+        }
+
+        for (_, systems) in self.stages() {
+            for batch in independent_batches(systems) {
+                if batch.len() < 2 {
+                    continue;
+                }
+
+                let accesses: Vec<_> = batch.iter().map(|entry| entry.system.access()).collect();
+                for (i, access) in accesses.iter().enumerate() {
+                    let access = match access {
+                        Some(access) => access,
+                        None => {
+                            errors.push(ScheduleError::UnknownAccess {
+                                system: batch[i].system.name(),
+                            });
+                            continue;
+                        }
+                    };
+                    for (j, other) in accesses.iter().enumerate().skip(i + 1) {
+                        if other
+                            .as_ref()
+                            .map_or(false, |other| access.conflicts_with(other))
+                        {
+                            errors.push(ScheduleError::Conflict {
+                                first: batch[i].system.name(),
+                                second: batch[j].system.name(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the schedule as a Graphviz graph in DOT format.
+    ///
+    /// Systems are grouped into a cluster per stage and colored by their
+    /// world access: red for exclusive, blue for `WorldAccess::Read`, and
+    /// green for `WorldAccess::Unused`. Solid edges show ordering constraints;
+    /// dashed red edges connect systems in the same stage whose resource
+    /// accesses conflict. Ordering is final once `set_up` has been called.
+    pub fn schedule_dot(&self) -> String {
+        use std::fmt::Write as _;
+
+        let orderings: Vec<_> = self
+            .systems
+            .iter()
+            .map(|entry| entry.system.ordering())
+            .collect();
+        let accesses: Vec<_> = self
+            .systems
+            .iter()
+            .map(|entry| entry.system.access())
+            .collect();
+
+        let mut dot = String::from("digraph schedule {\n    node [shape=box, style=filled];\n");
+        for stage in STAGES.iter() {
+            let indices: Vec<_> = (0..self.systems.len())
+                .filter(|&i| self.systems[i].stage == *stage)
+                .collect();
+            if indices.is_empty() {
+                continue;
+            }
+
+            writeln!(dot, "    subgraph cluster_{:?} {{", stage).unwrap();
+            writeln!(dot, "        label=\"{:?}\";", stage).unwrap();
+            for &i in &indices {
+                let system = &self.systems[i].system;
+                let mut label = escape_dot(system.name());
+                if !orderings[i].labels.is_empty() {
+                    write!(
+                        label,
+                        "\\n[{}]",
+                        escape_dot(&orderings[i].labels.join(", "))
+                    )
+                    .unwrap();
+                }
+                let color = match &accesses[i] {
+                    _ if system.is_exclusive() => "lightcoral",
+                    Some(access) if access.world == WorldAccess::Read => "lightblue",
+                    _ => "lightgreen",
+                };
+                writeln!(
+                    dot,
+                    "        s{} [label=\"{}\", fillcolor={}];",
+                    i, label, color
+                )
+                .unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+
+            for &i in &indices {
+                for &j in &indices {
+                    if i != j
+                        && precedes(
+                            &self.systems[i],
+                            &orderings[i],
+                            &self.systems[j],
+                            &orderings[j],
+                        )
+                    {
+                        writeln!(dot, "    s{} -> s{};", i, j).unwrap();
+                    }
+                    if i < j {
+                        if let (Some(a), Some(b)) = (&accesses[i], &accesses[j]) {
+                            if a.conflicts_with(b) {
+                                writeln!(
+                                    dot,
+                                    "    s{} -> s{} [style=dashed, color=red, arrowhead=none];",
+                                    i, j
+                                )
+                                .unwrap();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the name and recorded timings of each system, in execution order.
+    pub fn timings(&self) -> Vec<(&'static str, SystemTimings)> {
+        self.systems
+            .iter()
+            .map(|entry| {
+                let timings = *entry.timings.lock().expect("system timings poisoned");
+                (entry.system.name(), timings)
+            })
+            .collect()
+    }
+
     /// Setsup each system registred for this executor.
     ///
+    /// Systems are first sorted within each stage to satisfy
+    /// their ordering constraints (see `RawSystem::ordering`).
+    /// Unconstrained systems keep the order they were added in.
+    ///
+    /// If the ordering constraints contain a cycle, the systems in the
+    /// cycle keep the order they were added in, and the cycle is
+    /// logged and reported by `validate`.
+    ///
     /// # Note
     /// This function should only be called once.
     pub fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
-        for system in &mut self.systems {
-            system.set_up(resources, world);
+        self.sort();
+
+        for entry in &mut self.systems {
+            entry.system.set_up(resources, world);
+        }
+        for entry in &mut self.async_systems {
+            entry.system.set_up(resources, world);
+        }
+        if let Some(handlers) = &mut self.event_handlers {
+            handlers.set_up(resources, world);
+        }
+
+        if self.manage_time {
+            if !resources.contains::<Tick>() {
+                resources.insert(Tick::default());
+            }
+            if !resources.contains::<Time>() {
+                resources.insert(Time::default());
+            }
+        }
+    }
+
+    /// Sorts the systems within each stage to satisfy their ordering
+    /// constraints, recording any cycles in `ordering_errors`.
+    fn sort(&mut self) {
+        self.ordering_errors.clear();
+        let mut systems = std::mem::take(&mut self.systems).into_iter().peekable();
+        while let Some(first) = systems.next() {
+            let stage = first.stage;
+            let mut stage_systems = vec![first];
+            while let Some(entry) = systems.next_if(|entry| entry.stage == stage) {
+                stage_systems.push(entry);
+            }
+
+            let (sorted, cycle) = sort_by_ordering(stage_systems);
+            self.systems.extend(sorted);
+            if let Some(cycle) = cycle {
+                log::error!("{}", cycle);
+                self.ordering_errors.push(cycle);
+            }
+        }
+    }
+
+    /// Applies the given configuration to the systems with the names it lists,
+    /// then re-sorts the systems within each stage.
+    ///
+    /// Ordering constraints from the configuration replace those from any
+    /// previously applied configuration. Systems moved to another stage keep
+    /// their order relative to the systems added before and after them.
+    ///
+    /// Returns an error for each name which matches no system;
+    /// the rest of the configuration is still applied.
+    pub fn apply_config(&mut self, config: &ScheduleConfig) -> Result<(), Vec<ScheduleError>> {
+        let mut errors = vec![];
+
+        for (name, system_config) in &config.systems {
+            let mut found = false;
+            for entry in self.systems.iter_mut().filter(|entry| entry.has_name(name)) {
+                if let Some(enabled) = system_config.enabled {
+                    *entry.enabled.get_mut() = enabled;
+                }
+                if let Some(stage) = system_config.stage {
+                    entry.stage = stage;
+                }
+                entry.config_before = system_config.before.clone();
+                entry.config_after = system_config.after.clone();
+                found = true;
+            }
+
+            if !found {
+                errors.push(ScheduleError::UnknownSystem { name: name.clone() });
+            }
+        }
+
+        self.systems.sort_by_key(|entry| entry.stage);
+        self.sort();
+        errors.extend(self.ordering_errors.iter().cloned());
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
     /// Executes the systems in series.
+    ///
+    /// Submitted commands and deferred despawns are flushed after each system.
+    /// Errors returned by systems are handled according
+    /// to the executor's `ErrorPolicy`.
+    ///
+    /// # Panics
+    /// Panics if a thread-local system is registered;
+    /// use `execute_thread_local` instead.
+    pub fn execute(&self, resources: &impl ResourcesProvider, world: &mut World) {
+        self.run_tick(world, |world| {
+            let _ = self.execute_inner(&resources.as_resources_ref(), None, world, None);
+        });
+    }
+
+    /// Executes the systems in series, passing `thread_local`
+    /// to systems marked as thread-local.
+    pub fn execute_thread_local(
+        &self,
+        resources: &impl ResourcesProvider,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+    ) {
+        self.run_tick(world, |world| {
+            let _ = self.execute_inner(
+                &resources.as_resources_ref(),
+                Some(thread_local),
+                world,
+                None,
+            );
+        });
+    }
+
+    /// Executes the systems in series on behalf of a system of `outer`,
+    /// applying the error policy of `outer` rather than this executor's.
+    ///
+    /// Under `ErrorPolicy::Log`, errors are logged and the remaining
+    /// systems run. Otherwise the first error is returned, so that
+    /// `outer` aborts its tick or panics.
+    pub(crate) fn try_execute_nested(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: Option<&ThreadLocalResources>,
+        world: &mut World,
+        outer: &Executor,
+    ) -> Result<(), SystemError> {
+        let mut result = Ok(());
+        self.run_tick(world, |world| {
+            result = self.execute_inner(resources, thread_local, world, Some(outer.error_policy));
+        });
+        result
+    }
+
+    /// Executes the systems, running consecutive systems in parallel
+    /// when their declared accesses do not conflict.
+    ///
+    /// Systems run in batches. A system whose access conflicts with
+    /// an earlier system in the current batch starts a new batch, so
+    /// conflicting systems still run in the order they were added.
+    /// Systems needing `&mut World` or with unknown access run alone.
+    ///
+    /// Batches never span more than one stage.
+    /// Submitted commands and deferred despawns are flushed after each batch;
+    /// changes submitted within one batch are applied in an unspecified order
+    /// unless enabled with `set_deterministic`.
+    ///
+    /// # Panics
+    /// Panics if a thread-local system is registered.
+    #[cfg(feature = "parallel")]
+    pub fn execute_parallel(&self, resources: &impl ResourcesProvider, world: &mut World) {
+        let resources = resources.as_resources_ref();
+        let profiling = self.profiling.load(Ordering::Relaxed);
+        let tick_start = Instant::now();
+        self.advance_time(&resources);
+
+        self.run_tick(world, |world| {
+            for (stage, systems) in self.stages() {
+                self.start_async(stage, &resources, world);
+
+                for batch in batches(systems) {
+                    if !self.run_batch(batch, &resources, world, None, profiling, tick_start) {
+                        return;
+                    }
+                }
+
+                self.join_async(stage, world);
+                self.dispatch_events(stage, &resources, world);
+            }
+        });
+    }
+
+    /// Executes the systems in series, except that consecutive systems
+    /// marked with `set_independent` run concurrently on the given pool.
+    ///
+    /// Unlike `execute_parallel`, declared accesses are not checked, which
+    /// allows adopting parallelism one system at a time. Independent systems
+    /// must not conflict in their resource borrows. Exclusive systems
+    /// (see `RawSystem::is_exclusive`) always run alone.
+    ///
+    /// Submitted commands and deferred despawns are flushed after each
+    /// system or run of independent systems.
+    ///
+    /// # Panics
+    /// Panics if a thread-local system is registered.
+    #[cfg(feature = "parallel")]
+    pub fn execute_chunked(
+        &self,
+        resources: &impl ResourcesProvider,
+        world: &mut World,
+        pool: &rayon::ThreadPool,
+    ) {
+        let resources = resources.as_resources_ref();
+        let profiling = self.profiling.load(Ordering::Relaxed);
+        let tick_start = Instant::now();
+        self.advance_time(&resources);
+
+        self.run_tick(world, |world| {
+            for (stage, systems) in self.stages() {
+                self.start_async(stage, &resources, world);
+
+                for batch in independent_batches(systems) {
+                    if !self.run_batch(batch, &resources, world, Some(pool), profiling, tick_start)
+                    {
+                        return;
+                    }
+                }
+
+                self.join_async(stage, world);
+                self.dispatch_events(stage, &resources, world);
+            }
+        });
+    }
+
+    /// Runs a batch of systems, concurrently if it contains more than one,
+    /// then flushes submitted commands and deferred despawns.
+    ///
+    /// Returns whether the remaining systems should run this tick.
+    #[cfg(feature = "parallel")]
+    fn run_batch(
+        &self,
+        batch: &[SystemEntry],
+        resources: &ResourcesEnum,
+        world: &mut World,
+        pool: Option<&rayon::ThreadPool>,
+        profiling: bool,
+        tick_start: Instant,
+    ) -> bool {
+        let proceed = match batch {
+            [entry] if !self.should_run(entry, resources, tick_start) => return true,
+            [entry] => {
+                assert!(
+                    !entry.system.is_thread_local(),
+                    "thread-local systems cannot be executed in parallel"
+                );
+                let result = self.timed(entry, profiling, || {
+                    entry.system.try_run(resources, world, self)
+                });
+                self.handle_result(entry, result)
+            }
+            batch => {
+                let world = &*world;
+                let run = || -> Vec<_> {
+                    batch
+                        .par_iter()
+                        .enumerate()
+                        .filter(|(_, entry)| self.should_run(entry, resources, tick_start))
+                        .map(|(i, entry)| {
+                            let run = || {
+                                self.timed(entry, profiling, || {
+                                    entry.system.try_run_shared(resources, world, self)
+                                })
+                            };
+                            // Deferred changes are flushed in system order rather than
+                            // in the order the systems happened to finish.
+                            let result = if self.deterministic {
+                                crate::world::with_submission_key(i, run)
+                            } else {
+                                run()
+                            };
+                            (entry, result)
+                        })
+                        .collect()
+                };
+                let results = match pool {
+                    Some(pool) => pool.install(run),
+                    None => run(),
+                };
+
+                // Every error in the batch is handled, even if an earlier one aborts the tick.
+                results.into_iter().fold(true, |proceed, (entry, result)| {
+                    self.handle_result(entry, result) && proceed
+                })
+            }
+        };
+        world.flush_commands();
+        world.flush_despawns();
+        proceed
+    }
+
+    /// Executes the systems in series. Errors are handled according to
+    /// `outer_policy` if the executor is nested in another one;
+    /// see `try_execute_nested`.
+    fn execute_inner(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: Option<&ThreadLocalResources>,
+        world: &mut World,
+        outer_policy: Option<ErrorPolicy>,
+    ) -> Result<(), SystemError> {
+        let profiling = self.profiling.load(Ordering::Relaxed);
+        let tick_start = Instant::now();
+        self.advance_time(resources);
+
+        for (stage, systems) in self.stages() {
+            self.start_async(stage, resources, world);
+
+            for entry in systems {
+                if !self.should_run(entry, resources, tick_start) {
+                    continue;
+                }
+
+                let system = &entry.system;
+                let result = self.timed(entry, profiling, || {
+                    if system.is_thread_local() {
+                        let thread_local = thread_local.expect(
+                            "thread-local systems must be executed with `Executor::execute_thread_local`",
+                        );
+                        system.try_run_thread_local(resources, thread_local, world, self)
+                    } else {
+                        system.try_run(resources, world, self)
+                    }
+                });
+                world.flush_commands();
+                world.flush_despawns();
+
+                match (outer_policy, result) {
+                    (_, Ok(())) => {}
+                    (Some(ErrorPolicy::Log), Err(error)) => {
+                        log_system_error(system.name(), &error);
+                    }
+                    (Some(_), Err(error)) => {
+                        return Err(format!("system {} failed: {}", system.name(), error).into());
+                    }
+                    (None, result) => {
+                        if !self.handle_result(entry, result) {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+
+            self.join_async(stage, world);
+            self.dispatch_events(stage, resources, world);
+        }
+        Ok(())
+    }
+
+    /// Runs `f` for the given system, measuring its duration
+    /// if profiling is enabled or a watchdog is set.
+    fn timed<R>(&self, entry: &SystemEntry, profiling: bool, f: impl FnOnce() -> R) -> R {
+        if !profiling && self.watchdog.is_none() {
+            return f();
+        }
+
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+
+        if profiling {
+            entry
+                .timings
+                .lock()
+                .expect("system timings poisoned")
+                .record(elapsed);
+        }
+        self.watch(entry, elapsed);
+        result
+    }
+
+    /// Reports the run of a system to the watchdog, if any,
+    /// disabling the system if it has been slow too often.
+    fn watch(&self, entry: &SystemEntry, elapsed: Duration) {
+        let watchdog = match &self.watchdog {
+            Some(watchdog) => watchdog,
+            None => return,
+        };
+
+        if elapsed <= watchdog.threshold {
+            entry.slow_runs.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        let name = entry.system.name();
+        match &watchdog.on_slow {
+            Some(on_slow) => on_slow(name, elapsed),
+            None => log::warn!(
+                "system {} took {:?}, exceeding {:?}",
+                name,
+                elapsed,
+                watchdog.threshold
+            ),
+        }
+
+        let slow_runs = entry.slow_runs.fetch_add(1, Ordering::Relaxed) + 1;
+        if watchdog
+            .disable_after
+            .map_or(false, |runs| slow_runs >= runs)
+        {
+            log::warn!(
+                "disabling system {} after {} consecutive slow runs",
+                name,
+                slow_runs
+            );
+            entry.enabled.store(false, Ordering::Relaxed);
+            entry.slow_runs.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns whether a system should run, counting a skip if it is
+    /// low-priority and the tick has exceeded the time budget.
+    fn should_run(
+        &self,
+        entry: &SystemEntry,
+        resources: &ResourcesEnum,
+        tick_start: Instant,
+    ) -> bool {
+        if !entry.should_run(resources) {
+            return false;
+        }
+
+        let over_budget = entry.low_priority
+            && self
+                .time_budget
+                .map_or(false, |budget| tick_start.elapsed() > budget);
+        if over_budget {
+            entry.budget_skips.fetch_add(1, Ordering::Relaxed);
+        }
+        !over_budget
+    }
+
+    /// Increments the `Tick` and advances the `Time` resources,
+    /// if they are present and the executor manages time.
+    fn advance_time(&self, resources: &ResourcesEnum) {
+        if !self.manage_time {
+            return;
+        }
+
+        let now = Instant::now();
+        let previous = self
+            .last_tick
+            .lock()
+            .expect("tick time poisoned")
+            .replace(now);
+
+        if let Ok(mut tick) = resources.try_get_mut::<Tick>() {
+            if previous.is_some() {
+                tick.0 += 1;
+            }
+        }
+        if let Ok(mut time) = resources.try_get_mut::<Time>() {
+            time.advance(previous.map_or(Duration::from_secs(0), |previous| now - previous));
+        }
+    }
+
+    /// Runs a tick with `f`. Unless this executor is executed by a
+    /// system of another executor, or has event handlers, the events
+    /// still queued at the end of the tick are dropped.
+    fn run_tick(&self, world: &mut World, f: impl FnOnce(&mut World)) {
+        /// Leaves the executor, even if `f` panics.
+        struct Leave;
+
+        impl Drop for Leave {
+            fn drop(&mut self) {
+                EXECUTION_DEPTH.with(|depth| depth.set(depth.get() - 1));
+            }
+        }
+
+        let outermost = EXECUTION_DEPTH.with(|depth| depth.replace(depth.get() + 1)) == 0;
+        let leave = Leave;
+        f(world);
+        drop(leave);
+
+        if outermost && self.event_handlers.is_none() {
+            let dropped = world.take_queued_events().len();
+            if dropped > 0 && !self.warned_unhandled_events.swap(true, Ordering::Relaxed) {
+                log::warn!(
+                    "dropped {} queued events because the executor has no event handlers",
+                    dropped
+                );
+            }
+        }
+    }
+
+    /// Dispatches queued events if `stage` is an event sync point.
+    fn dispatch_events(&self, stage: Stage, resources: &ResourcesEnum, world: &mut World) {
+        if let Some(handlers) = &self.event_handlers {
+            if self.event_sync_points.contains(&stage) {
+                handlers.dispatch_queued(resources, world);
+            }
+        }
+    }
+
+    /// Returns each stage along with its systems, in execution order.
+    fn stages(&self) -> impl Iterator<Item = (Stage, &[SystemEntry])> {
+        STAGES.iter().map(move |&stage| {
+            let start = self.systems.partition_point(|entry| entry.stage < stage);
+            let end = self.systems.partition_point(|entry| entry.stage <= stage);
+            (stage, &self.systems[start..end])
+        })
+    }
+
+    /// Starts the async systems which start at the beginning of `stage`.
+    ///
+    /// A task left over from a tick which was aborted before
+    /// its sync point is joined first.
+    fn start_async(&self, stage: Stage, resources: &ResourcesEnum, world: &mut World) {
+        for entry in self
+            .async_systems
+            .iter()
+            .filter(|entry| entry.stage == stage)
+        {
+            entry.join(world);
+
+            let future = entry.system.start(resources, world);
+            let task_pool = self.task_pool.get_or_init(|| {
+                let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+                TaskPool::new(threads)
+            });
+            *entry.task.lock().expect("async task poisoned") = Some(task_pool.spawn(future));
+        }
+    }
+
+    /// Joins the async systems which sync at the end of `stage`.
+    fn join_async(&self, stage: Stage, world: &mut World) {
+        for entry in self
+            .async_systems
+            .iter()
+            .filter(|entry| entry.sync == stage)
+        {
+            entry.join(world);
+        }
+    }
+
+    /// Applies the error policy to the result of running a system.
+    ///
+    /// Returns whether the remaining systems should run this tick.
+    fn handle_result(&self, entry: &SystemEntry, result: Result<(), SystemError>) -> bool {
+        let error = match result {
+            Ok(()) => return true,
+            Err(error) => error,
+        };
+
+        let name = entry.system.name();
+        match self.error_policy {
+            ErrorPolicy::Log => {
+                log_system_error(name, &error);
+                true
+            }
+            ErrorPolicy::AbortTick => {
+                log::error!("system {} failed, aborting tick: {}", name, error);
+                false
+            }
+            ErrorPolicy::Panic => panic!("system {} failed: {}", name, error),
+        }
+    }
+}
+
+#[cfg(feature = "dylib")]
+impl Executor {
+    /// Loads a system library, which exports its systems with
+    /// `export_systems!`, and sets up and adds the systems it registers.
+    ///
+    /// Loaded systems are placed at the end of their stage, then the
+    /// systems are re-sorted to satisfy their ordering constraints.
+    ///
+    /// The library is loaded from a copy in the temporary directory,
+    /// which is removed when the library is unloaded.
+    ///
+    /// # Safety
+    /// The library must be built with the same compiler and version
+    /// of fecs, and must be sound to load; see `libloading::Library::new`.
+    pub unsafe fn load_library(
+        &mut self,
+        path: impl AsRef<Path>,
+        resources: &mut OwnedResources,
+        world: &mut World,
+    ) -> Result<LibraryId, LibraryError> {
+        let (library, systems) = LoadedLibrary::load(None, path.as_ref())?;
+        let id = library.id;
+        self.libraries.push(library);
+
+        for (stage, mut system) in systems {
+            system.set_up(resources, world);
+            let index = self.insert_entry(stage, system);
+            self.systems[index].library = Some(id);
+        }
+        self.sort();
+        Ok(id)
+    }
+
+    /// Removes the systems registered by a library and unloads it.
+    pub fn unload_library(&mut self, id: LibraryId) -> Result<(), LibraryError> {
+        let index = self.library_index(id)?;
+        self.systems.retain(|entry| entry.library != Some(id));
+        self.libraries.remove(index);
+        Ok(())
+    }
+
+    /// Loads a new version of a library from the path it was loaded from,
+    /// then unloads the old version.
+    ///
+    /// The library file may be replaced while it is loaded, as each version
+    /// is loaded from its own copy.
+    ///
+    /// Systems of the new version replace systems of the same name in place,
+    /// keeping their position, stage, run criteria, and enabled state.
+    /// If several systems share a name, the new systems, in the order
+    /// the library registers them, each replace the first old system
+    /// of that name in execution order which was not already replaced.
+    /// Other new systems are added as in `load_library`, and old
+    /// systems missing from the new version are removed. The systems
+    /// are then re-sorted, as the ordering constraints may have changed.
+    ///
+    /// # Safety
+    /// See `load_library`.
+    pub unsafe fn reload_library(
+        &mut self,
+        id: LibraryId,
+        resources: &mut OwnedResources,
+        world: &mut World,
+    ) -> Result<(), LibraryError> {
+        let index = self.library_index(id)?;
+        let path = self.libraries[index].path.clone();
+        let (library, systems) = LoadedLibrary::load(Some(id), &path)?;
+
+        let mut replaced = vec![false; self.systems.len()];
+        let mut added = vec![];
+        for (stage, mut system) in systems {
+            system.set_up(resources, world);
+
+            let old = self.systems.iter().enumerate().position(|(i, entry)| {
+                entry.library == Some(id) && !replaced[i] && entry.system.name() == system.name()
+            });
+            match old {
+                Some(i) => {
+                    replaced[i] = true;
+                    let entry = &mut self.systems[i];
+                    *entry.timings.get_mut().expect("system timings poisoned") =
+                        SystemTimings::default();
+                    entry.system = system;
+                }
+                None => added.push((stage, system)),
+            }
+        }
+
+        let mut replaced = replaced.into_iter();
+        self.systems.retain(|entry| {
+            let replaced = replaced.next().unwrap_or(false);
+            entry.library != Some(id) || replaced
+        });
+        for (stage, system) in added {
+            let index = self.insert_entry(stage, system);
+            self.systems[index].library = Some(id);
+        }
+
+        self.sort();
+
+        // Drops the old version, now that none of its systems remain.
+        self.libraries[index] = library;
+        Ok(())
+    }
+
+    fn library_index(&self, id: LibraryId) -> Result<usize, LibraryError> {
+        self.libraries
+            .iter()
+            .position(|library| library.id == id)
+            .ok_or(LibraryError::NotLoaded(id))
+    }
+}
+
+/// An `Executor` stored as a resource, so that systems can run it
+/// as a nested schedule against the same world, e.g. for the phases
+/// of a turn or a scripted cutscene.
+///
+/// The marker type `K` distinguishes multiple sub-executors stored
+/// in the same resources. A system runs the sub-executor by taking
+/// it along with the `ResourcesEnum` and `&mut World` as parameters:
+///
+/// ```ignore
+/// #[system]
+/// fn run_cutscene(cutscene: &SubExecutor<Cutscene>, resources: &ResourcesEnum, world: &mut World) {
+///     cutscene.execute(resources, world);
+/// }
+/// ```
+///
+/// Systems of the sub-executor may not borrow it mutably.
+pub struct SubExecutor<K = ()> {
+    executor: Executor,
+    _marker: PhantomData<fn() -> K>,
+}
+
+impl<K> SubExecutor<K>
+where
+    K: 'static,
+{
+    /// Wraps the given executor, disabling its management
+    /// of the `Tick` and `Time` resources.
+    pub fn new(executor: Executor) -> Self {
+        Self {
+            executor: executor.with_manage_time(false),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets up the sub-executor and inserts it into the resources.
+    pub fn insert_into(mut self, resources: &mut OwnedResources, world: &mut World) {
+        self.executor.set_up(resources, world);
+        resources.insert(self);
+    }
+
+    /// Executes the systems of the sub-executor in series.
     pub fn execute(&self, resources: &impl ResourcesProvider, world: &mut World) {
-        for system in &self.systems {
-            system.run(&resources.as_resources_ref(), world, self);
+        self.executor.execute(resources, world);
+    }
+
+    /// Returns the wrapped executor.
+    pub fn executor(&self) -> &Executor {
+        &self.executor
+    }
+
+    /// Mutably returns the wrapped executor.
+    pub fn executor_mut(&mut self) -> &mut Executor {
+        &mut self.executor
+    }
+}
+
+/// A problem with a schedule found by `Executor::validate`.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("system {system} requires resource {type_id:?}, which was not inserted")]
+    MissingResource {
+        system: &'static str,
+        type_id: TypeId,
+    },
+    #[error("independent systems {first} and {second} have conflicting accesses")]
+    Conflict {
+        first: &'static str,
+        second: &'static str,
+    },
+    #[error("independent system {system} does not declare its access")]
+    UnknownAccess { system: &'static str },
+    #[error("no system is named {name}")]
+    UnknownSystem { name: String },
+    #[error("cycle in system ordering constraints between {systems:?}")]
+    OrderingCycle { systems: Vec<&'static str> },
+}
+
+/// Builds an `Executor`, validating the schedule before it is used.
+#[derive(Default)]
+pub struct ExecutorBuilder {
+    executor: Executor,
+}
+
+impl ExecutorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given system.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with(mut self, system: impl RawSystem) -> Self {
+        self.executor.add(system);
+        self
+    }
+
+    /// Adds the given system to the given stage.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with_stage(mut self, stage: Stage, system: impl RawSystem) -> Self {
+        self.executor.add_to_stage(stage, system);
+        self
+    }
+
+    /// Adds the given system, marked as independent; see `Executor::set_independent`.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with_independent(mut self, system: impl RawSystem) -> Self {
+        let stage = system.stage();
+        let index = self.executor.insert_entry(stage, Box::new(system));
+        self.executor.systems[index].independent = true;
+        self
+    }
+
+    /// Sets up the executor and validates its schedule with `Executor::validate`.
+    pub fn build(
+        mut self,
+        resources: &mut OwnedResources,
+        world: &mut World,
+    ) -> Result<Executor, Vec<ScheduleError>> {
+        self.executor.set_up(resources, world);
+        self.executor.validate(resources)?;
+        Ok(self.executor)
+    }
+}
+
+/// A named group of systems which runs as a single system.
+///
+/// The group is named and labeled after `name`, so that it can be
+/// enabled with `Executor::set_enabled`, ordered against with
+/// `before` and `after`, and profiled as a whole. Its systems run
+/// in the order they were added, and never in parallel.
+///
+/// Errors returned by its systems are handled according to the
+/// `ErrorPolicy` of the executor running the group: under
+/// `ErrorPolicy::Log` they are logged and the group continues,
+/// otherwise the group stops and returns the error to that executor.
+pub struct SystemGroup {
+    name: &'static str,
+    systems: Executor,
+    ordering: SystemOrdering,
+}
+
+impl SystemGroup {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            systems: Executor::new(),
+            ordering: SystemOrdering {
+                labels: vec![name],
+                ..SystemOrdering::default()
+            },
         }
     }
+
+    /// Adds the given system to the group.
+    pub fn add(&mut self, system: impl RawSystem) {
+        self.systems.add(system);
+    }
+
+    /// Adds the given system to the group.
+    pub fn add_boxed(&mut self, system: Box<dyn RawSystem>) {
+        self.systems.add_boxed(system);
+    }
+
+    /// Adds the given system to the group.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with(mut self, system: impl RawSystem) -> Self {
+        self.add(system);
+        self
+    }
+
+    /// Makes the group run before systems with the given label.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.ordering.before.push(label);
+        self
+    }
+
+    /// Makes the group run after systems with the given label.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.ordering.after.push(label);
+        self
+    }
+}
+
+impl RawSystem for SystemGroup {
+    fn run(&self, resources: &ResourcesEnum, world: &mut World, executor: &Executor) {
+        if let Err(error) = self.try_run(resources, world, executor) {
+            log_system_error(self.name, &error);
+        }
+    }
+
+    fn try_run(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.systems
+            .try_execute_nested(resources, None, world, executor)
+    }
+
+    fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
+        self.systems.set_up(resources, world);
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn ordering(&self) -> SystemOrdering {
+        self.ordering.clone()
+    }
+
+    fn is_thread_local(&self) -> bool {
+        self.systems
+            .systems
+            .iter()
+            .any(|entry| entry.system.is_thread_local())
+    }
+
+    fn run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) {
+        if let Err(error) = self.try_run_thread_local(resources, thread_local, world, executor) {
+            log_system_error(self.name, &error);
+        }
+    }
+
+    fn try_run_thread_local(
+        &self,
+        resources: &ResourcesEnum,
+        thread_local: &ThreadLocalResources,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        self.systems
+            .try_execute_nested(resources, Some(thread_local), world, executor)
+    }
+}
+
+/// Splits the systems of a stage into batches of consecutive
+/// independent systems, with every other system alone in its batch.
+fn independent_batches(systems: &[SystemEntry]) -> Vec<&[SystemEntry]> {
+    let concurrent = |entry: &SystemEntry| entry.independent && !entry.system.is_exclusive();
+
+    let mut batches = vec![];
+    let mut rest = systems;
+    while let Some(first) = rest.first() {
+        let len = if concurrent(first) {
+            rest.iter().take_while(|entry| concurrent(entry)).count()
+        } else {
+            1
+        };
+        let (batch, remaining) = rest.split_at(len);
+        batches.push(batch);
+        rest = remaining;
+    }
+    batches
+}
+
+/// Splits the systems of a stage into batches
+/// of consecutive systems which can run in parallel.
+#[cfg(feature = "parallel")]
+fn batches(systems: &[SystemEntry]) -> Vec<&[SystemEntry]> {
+    let mut batches = vec![];
+    let mut start = 0;
+    let mut batch_access: Vec<SystemAccess> = vec![];
+
+    for (i, entry) in systems.iter().enumerate() {
+        let system = &entry.system;
+        let access = system.access().filter(|_| !system.is_exclusive());
+
+        match access {
+            Some(access)
+                if batch_access
+                    .iter()
+                    .all(|other| !access.conflicts_with(other)) =>
+            {
+                batch_access.push(access);
+            }
+            Some(access) => {
+                batches.push(&systems[start..i]);
+                start = i;
+                batch_access = vec![access];
+            }
+            None => {
+                if start < i {
+                    batches.push(&systems[start..i]);
+                }
+                batches.push(&systems[i..=i]);
+                start = i + 1;
+                batch_access.clear();
+            }
+        }
+    }
+
+    if start < systems.len() {
+        batches.push(&systems[start..]);
+    }
+    batches
+}
+
+/// Escapes `s` for use in a quoted DOT string.
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Returns whether system `a` must run before system `b`, according
+/// to their ordering constraints and any applied `ScheduleConfig`.
+fn precedes(
+    a: &SystemEntry,
+    a_ordering: &SystemOrdering,
+    b: &SystemEntry,
+    b_ordering: &SystemOrdering,
+) -> bool {
+    a_ordering.precedes(b_ordering)
+        || a.config_before
+            .iter()
+            .any(|label| b.matches(label, b_ordering))
+        || b.config_after
+            .iter()
+            .any(|label| a.matches(label, a_ordering))
+}
+
+/// Sorts systems topologically according to their ordering constraints,
+/// preferring the existing order among unconstrained systems.
+///
+/// Systems in or after a cycle are placed last, in their existing
+/// order, and the cycle is returned as an error.
+fn sort_by_ordering(systems: Vec<SystemEntry>) -> (Vec<SystemEntry>, Option<ScheduleError>) {
+    let orderings: Vec<_> = systems
+        .iter()
+        .map(|entry| entry.system.ordering())
+        .collect();
+
+    // successors[i] holds the systems which must run after system i.
+    let mut successors = vec![vec![]; systems.len()];
+    let mut predecessor_counts = vec![0; systems.len()];
+    for (i, ordering) in orderings.iter().enumerate() {
+        for (j, other) in orderings.iter().enumerate() {
+            if i != j && precedes(&systems[i], ordering, &systems[j], other) {
+                successors[i].push(j);
+                predecessor_counts[j] += 1;
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<_> = (0..systems.len())
+        .filter(|&i| predecessor_counts[i] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(systems.len());
+    while let Some(Reverse(i)) = ready.pop() {
+        order.push(i);
+        for &j in &successors[i] {
+            predecessor_counts[j] -= 1;
+            if predecessor_counts[j] == 0 {
+                ready.push(Reverse(j));
+            }
+        }
+    }
+
+    let mut cycle = None;
+    if order.len() < systems.len() {
+        let unsorted: Vec<_> = (0..systems.len())
+            .filter(|&i| predecessor_counts[i] > 0)
+            .collect();
+        cycle = Some(ScheduleError::OrderingCycle {
+            systems: unsorted
+                .iter()
+                .map(|&i| {
+                    orderings[i]
+                        .labels
+                        .first()
+                        .copied()
+                        .unwrap_or_else(|| systems[i].system.name())
+                })
+                .collect(),
+        });
+        order.extend(unsorted);
+    }
+
+    let mut systems: Vec<_> = systems.into_iter().map(Some).collect();
+    let sorted = order
+        .into_iter()
+        .map(|i| systems[i].take().unwrap())
+        .collect();
+    (sorted, cycle)
 }
 
 static_assertions::assert_impl_all!(Executor: Send, Sync);
@@ -0,0 +1,159 @@
+//! Tick timing and fixed-timestep execution.
+
+use crate::{
+    log_system_error, Executor, OwnedResources, RawSystem, ResourcesEnum, ResourcesProvider,
+    SystemError, World,
+};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The number of the current tick, starting at zero.
+///
+/// Maintained by an `Executor` for which it is enabled
+/// with `Executor::set_manage_time`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tick(pub u64);
+
+/// Timing information for the current tick.
+///
+/// Maintained by an `Executor` for which it is enabled with
+/// `Executor::set_manage_time`; otherwise the application
+/// should call `advance` once per tick.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Time {
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new tick which began `delta` after the previous one.
+    pub fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+
+    /// Returns the time between the previous tick and this one.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Returns the total time passed over all ticks.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// A system which runs its own set of systems at a fixed rate,
+/// independent of the rate at which it is executed.
+///
+/// Each time it runs, the `delta` of the `Time` resource is
+/// accumulated, and the inner systems are run once for every
+/// `step` of accumulated time, which may be zero or more times.
+///
+/// At most `max_steps` steps are run each time; if more time has
+/// accumulated, for example after a long pause, the excess is dropped
+/// rather than caught up on. Errors returned by the inner systems
+/// are handled as in a `SystemGroup`.
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    systems: Executor,
+    accumulated: Mutex<Duration>,
+}
+
+impl FixedTimestep {
+    /// The default maximum number of steps run each time.
+    pub const DEFAULT_MAX_STEPS: u32 = 8;
+
+    /// Creates a `FixedTimestep` which runs its systems once per `step`.
+    ///
+    /// # Panics
+    /// Panics if `step` is zero.
+    pub fn new(step: Duration) -> Self {
+        assert!(step > Duration::from_secs(0), "timestep must be nonzero");
+        Self {
+            step,
+            max_steps: Self::DEFAULT_MAX_STEPS,
+            systems: Executor::new(),
+            accumulated: Mutex::new(Duration::from_secs(0)),
+        }
+    }
+
+    /// Sets the maximum number of steps run each time.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps;
+    }
+
+    /// Sets the maximum number of steps run each time.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.set_max_steps(max_steps);
+        self
+    }
+
+    /// Adds the given system to run at the fixed rate.
+    pub fn add(&mut self, system: impl RawSystem) {
+        self.systems.add(system);
+    }
+
+    /// Adds the given system to run at the fixed rate.
+    ///
+    /// Returns `Self` such that method calls can be chained.
+    pub fn with(mut self, system: impl RawSystem) -> Self {
+        self.add(system);
+        self
+    }
+}
+
+impl RawSystem for FixedTimestep {
+    fn run(&self, resources: &ResourcesEnum, world: &mut World, executor: &Executor) {
+        if let Err(error) = self.try_run(resources, world, executor) {
+            log_system_error(self.name(), &error);
+        }
+    }
+
+    fn try_run(
+        &self,
+        resources: &ResourcesEnum,
+        world: &mut World,
+        executor: &Executor,
+    ) -> Result<(), SystemError> {
+        let steps = {
+            let mut accumulated = self.accumulated.lock().expect("timestep poisoned");
+            *accumulated += resources.get::<Time>().delta();
+
+            let mut steps = 0;
+            while *accumulated >= self.step {
+                *accumulated -= self.step;
+                steps += 1;
+            }
+            if steps > self.max_steps {
+                log::warn!(
+                    "fixed timestep fell behind, skipping {} steps",
+                    steps - self.max_steps
+                );
+                steps = self.max_steps;
+            }
+            steps
+        };
+
+        for _ in 0..steps {
+            self.systems
+                .try_execute_nested(resources, None, world, executor)?;
+        }
+        Ok(())
+    }
+
+    fn set_up(&mut self, resources: &mut OwnedResources, world: &mut World) {
+        self.systems.set_up(resources, world);
+    }
+
+    fn name(&self) -> &'static str {
+        "FixedTimestep"
+    }
+}
@@ -0,0 +1,99 @@
+//! Hashing configuration for the `TypeId`-keyed maps used throughout the crate.
+
+use fxhash::FxHasher;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hasher};
+
+/// Selects the hasher used by the `TypeId` maps in `OwnedResources`
+/// and `EventHandlers`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HasherKind {
+    /// The unkeyed `fxhash` hasher. This is the fastest option and the default.
+    Fx,
+    /// `fxhash` keyed with the given seed. Hashing is deterministic for a given seed.
+    SeededFx(u64),
+    /// The standard library's randomly keyed SipHash, for deployments
+    /// sensitive to HashDoS.
+    Random,
+}
+
+impl Default for HasherKind {
+    fn default() -> Self {
+        HasherKind::Fx
+    }
+}
+
+/// `BuildHasher` which dispatches to the hasher selected by a `HasherKind`.
+#[derive(Clone)]
+pub struct ConfiguredBuildHasher(BuildInner);
+
+#[derive(Clone)]
+enum BuildInner {
+    Fx(u64),
+    Random(RandomState),
+}
+
+impl ConfiguredBuildHasher {
+    pub fn new(kind: HasherKind) -> Self {
+        Self(match kind {
+            HasherKind::Fx => BuildInner::Fx(0),
+            HasherKind::SeededFx(seed) => BuildInner::Fx(seed),
+            HasherKind::Random => BuildInner::Random(RandomState::new()),
+        })
+    }
+}
+
+impl Default for ConfiguredBuildHasher {
+    fn default() -> Self {
+        Self::new(HasherKind::default())
+    }
+}
+
+impl BuildHasher for ConfiguredBuildHasher {
+    type Hasher = ConfiguredHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        match &self.0 {
+            BuildInner::Fx(seed) => {
+                let mut hasher = FxHasher::default();
+                if *seed != 0 {
+                    hasher.write_u64(*seed);
+                }
+                ConfiguredHasher::Fx(hasher)
+            }
+            BuildInner::Random(state) => ConfiguredHasher::Sip(state.build_hasher()),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub enum ConfiguredHasher {
+    Fx(FxHasher),
+    Sip(DefaultHasher),
+}
+
+impl Hasher for ConfiguredHasher {
+    fn finish(&self) -> u64 {
+        match self {
+            ConfiguredHasher::Fx(h) => h.finish(),
+            ConfiguredHasher::Sip(h) => h.finish(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            ConfiguredHasher::Fx(h) => h.write(bytes),
+            ConfiguredHasher::Sip(h) => h.write(bytes),
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        match self {
+            ConfiguredHasher::Fx(h) => h.write_u64(i),
+            ConfiguredHasher::Sip(h) => h.write_u64(i),
+        }
+    }
+}
+
+/// Hash map using the hasher configured through `WorldOptions`.
+pub(crate) type HashMap<K, V> = std::collections::HashMap<K, V, ConfiguredBuildHasher>;
@@ -1,27 +1,111 @@
+use crate::builder::{Bundle, EntityBuilder};
+use crate::commands::CommandBuffer;
 use crate::entity_ref::EntityRef;
+use crate::events::{Event, QueuedEvent};
 use crate::query::{Query, QueryBorrow};
+use crate::util::HasherKind;
 use legion::borrow::{Ref, RefMut};
 use legion::entity::Entity;
+use legion::filter::{ChunksetFilterData, Filter};
 use legion::query::IntoQuery;
-use legion::storage::Component;
-use legion::world::{ComponentTypeTupleSet, EntityMutationError, IntoComponentSource};
+use legion::storage::{Component, Tag};
+use legion::world::{
+    ComponentTypeTupleSet, EntityMutationError, IntoComponentSource, TagLayout, TagSet,
+};
+use std::cell::Cell;
+use std::sync::Mutex;
 
 type LegionWorld = legion::world::World;
 
+thread_local! {
+    /// Key ordering deferred changes submitted from the current thread;
+    /// see `with_submission_key`.
+    static SUBMISSION_KEY: Cell<usize> = Cell::new(0);
+}
+
+/// Runs `f` with the given submission key, so that deferred despawns,
+/// commands and events it queues are flushed after those queued with
+/// lower keys, regardless of when they were queued.
+pub(crate) fn with_submission_key<R>(key: usize, f: impl FnOnce() -> R) -> R {
+    /// Restores the previous key, even if `f` panics.
+    struct Restore(usize);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SUBMISSION_KEY.with(|current| current.set(self.0));
+        }
+    }
+
+    let _restore = Restore(SUBMISSION_KEY.with(|current| current.replace(key)));
+    f()
+}
+
+fn submission_key() -> usize {
+    SUBMISSION_KEY.with(Cell::get)
+}
+
+/// Configuration shared by a `World` and the containers used alongside it.
+///
+/// Pass the same options to `OwnedResources::with_options` and
+/// `EventHandlers::with_options` to configure them consistently.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WorldOptions {
+    /// The hasher used for `TypeId`-keyed maps.
+    pub hasher: HasherKind,
+}
+
+impl WorldOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the hasher used for `TypeId`-keyed maps.
+    ///
+    /// Returns `Self` such that method calls for `WorldOptions` can be chained.
+    pub fn hasher(mut self, hasher: HasherKind) -> Self {
+        self.hasher = hasher;
+        self
+    }
+}
+
 /// Contains queryable collections of data associated with `Entity`s.
 #[derive(Default)]
 pub struct World {
     inner: LegionWorld,
+    options: WorldOptions,
+    /// Entities queued for despawning by `despawn_deferred`.
+    deferred_despawns: Mutex<Vec<(usize, Entity)>>,
+    /// Command buffers queued by `submit_commands`.
+    queued_commands: Mutex<Vec<(usize, CommandBuffer)>>,
+    /// Events queued by `queue_event` since commands were last flushed.
+    queued_events: Mutex<Vec<(usize, QueuedEvent)>>,
+    /// Events queued before commands were last flushed, in dispatch order.
+    ordered_events: Mutex<Vec<QueuedEvent>>,
 }
 
 impl World {
     /// Creates a new Fecs World
     pub fn new() -> Self {
+        Self::with_options(WorldOptions::default())
+    }
+
+    /// Creates a new Fecs World with the given options.
+    pub fn with_options(options: WorldOptions) -> Self {
         World {
             inner: LegionWorld::default(),
+            options,
+            deferred_despawns: Mutex::new(Vec::new()),
+            queued_commands: Mutex::new(Vec::new()),
+            queued_events: Mutex::new(Vec::new()),
+            ordered_events: Mutex::new(Vec::new()),
         }
     }
 
+    /// Returns the options this world was created with.
+    pub fn options(&self) -> &WorldOptions {
+        &self.options
+    }
+
     /// Spawns multiple new entities into the world with the given components,
     /// the `EntityBuilder` and `BuiltEntity::spawn_in` is prefered for spawning
     /// a single entity. You can use the `EntityBuilder::build` to create multiple
@@ -32,13 +116,166 @@ impl World {
         self.inner.insert((), components)
     }
 
+    /// Spawns multiple new entities which share the given tags.
+    ///
+    /// Tags are stored once per chunk, so queries can filter on them with
+    /// `tag_value` without visiting each entity.
+    ///
+    /// Returns a slice of entity handlers for the spawned entities.
+    pub fn spawn_tagged<T>(&mut self, tags: T, components: impl IntoComponentSource) -> &[Entity]
+    where
+        T: TagSet + TagLayout + for<'a> Filter<ChunksetFilterData<'a>>,
+    {
+        self.inner.insert(tags, components)
+    }
+
+    /// Adds a tag to an entity, or sets its value if the tag is already present.
+    ///
+    /// # Notes
+    /// Like `add`, this moves the entity to a different chunk.
+    pub fn add_tag<T>(&mut self, entity: Entity, tag: T) -> Result<(), EntityMutationError>
+    where
+        T: Tag,
+    {
+        self.inner.add_tag(entity, tag)
+    }
+
+    /// Returns the value of the tag `T` for the given entity.
+    ///
+    /// Returns `None` if the entity was not found or does not have the tag.
+    pub fn get_tag<T>(&self, entity: Entity) -> Option<&T>
+    where
+        T: Tag,
+    {
+        self.inner.get_tag(entity)
+    }
+
     /// Despawns the given `Entity` from the `World`.
     ///
+    /// This requires that no component borrows into the world are live.
+    /// Use `despawn_deferred` when the entity may still be borrowed.
+    ///
     /// Returns `true` if the entity was despawned; else `false`.
     pub fn despawn(&mut self, entity: Entity) -> bool {
         self.inner.delete(entity)
     }
 
+    /// Queues the given `Entity` to be despawned from the `World`.
+    ///
+    /// This may be called while components of the entity are borrowed.
+    /// The entity stays alive until `flush_despawns` is called, which the
+    /// `Executor` does after each system.
+    pub fn despawn_deferred(&self, entity: Entity) {
+        self.deferred_despawns
+            .lock()
+            .expect("deferred despawn queue poisoned")
+            .push((submission_key(), entity));
+    }
+
+    /// Despawns all entities queued by `despawn_deferred`.
+    ///
+    /// Returns the number of entities which were despawned.
+    pub fn flush_despawns(&mut self) -> usize {
+        let mut queued = std::mem::take(
+            self.deferred_despawns
+                .get_mut()
+                .expect("deferred despawn queue poisoned"),
+        );
+        queued.sort_by_key(|(key, _)| *key);
+
+        queued
+            .into_iter()
+            .filter(|(_, entity)| self.inner.delete(*entity))
+            .count()
+    }
+
+    /// Queues the given `CommandBuffer` to be applied to the `World`.
+    ///
+    /// The commands are applied when `flush_commands` is called, which
+    /// the `Executor` does after each system.
+    pub fn submit_commands(&self, commands: CommandBuffer) {
+        if commands.is_empty() {
+            return;
+        }
+
+        self.queued_commands
+            .lock()
+            .expect("command queue poisoned")
+            .push((submission_key(), commands));
+    }
+
+    /// Applies all command buffers queued by `submit_commands`,
+    /// in the order they were submitted.
+    pub fn flush_commands(&mut self) {
+        let mut queued = std::mem::take(
+            self.queued_commands
+                .get_mut()
+                .expect("command queue poisoned"),
+        );
+        queued.sort_by_key(|(key, _)| *key);
+
+        // Events queued by the commands keep the key of their buffer.
+        for (key, commands) in queued {
+            with_submission_key(key, || commands.write(self));
+        }
+
+        self.order_events();
+    }
+
+    /// Queues the given event to be dispatched to event handlers later.
+    ///
+    /// Queued events are dispatched by `EventHandlers::dispatch_queued`,
+    /// which the `Executor` calls at its event sync points when it
+    /// has event handlers. An executor without handlers drops the
+    /// events at the end of its tick; see `Executor::set_event_handlers`.
+    ///
+    /// Events are dispatched in the order they were queued, even
+    /// if they have different types. When systems run in parallel,
+    /// events queued by systems in the same batch are ordered
+    /// like commands: in system order if the executor is
+    /// deterministic, otherwise in an unspecified order.
+    pub fn queue_event<E>(&self, event: E)
+    where
+        E: Event + Send,
+    {
+        self.queued_events
+            .lock()
+            .expect("event queue poisoned")
+            .push((submission_key(), QueuedEvent::new(event)));
+    }
+
+    /// Takes all events queued by `queue_event`, in dispatch order.
+    pub(crate) fn take_queued_events(&mut self) -> Vec<QueuedEvent> {
+        self.order_events();
+        std::mem::take(self.ordered_events.get_mut().expect("event queue poisoned"))
+    }
+
+    /// Appends the events queued since the last call to
+    /// `ordered_events`, ordered by submission key.
+    fn order_events(&mut self) {
+        let mut queued =
+            std::mem::take(self.queued_events.get_mut().expect("event queue poisoned"));
+        // Stable, so events with the same key keep their queue order.
+        queued.sort_by_key(|(key, _)| *key);
+        self.ordered_events
+            .get_mut()
+            .expect("event queue poisoned")
+            .extend(queued.into_iter().map(|(_, event)| event));
+    }
+
+    /// Spawns an entity for each of the given bundles,
+    /// returning the spawned entities in order.
+    pub fn add_batch<B>(&mut self, bundles: impl IntoIterator<Item = B>) -> Vec<Entity>
+    where
+        B: Bundle,
+    {
+        let mut builder = EntityBuilder::new();
+        bundles
+            .into_iter()
+            .map(|bundle| builder.add_bundle(bundle).build_one().spawn_in(self))
+            .collect()
+    }
+
     /// Adds a component to an entity, or sets its value if the component is already present.
     ///
     /// # Notes
@@ -223,4 +460,45 @@ impl World {
     pub fn inner_mut(&mut self) -> &mut LegionWorld {
         &mut self.inner
     }
+
+    /// Unwraps the `Legion::World` which `Fecs::World` is based on.
+    ///
+    /// Queued commands are applied and entities queued
+    /// by `despawn_deferred` are despawned first.
+    pub fn into_inner(mut self) -> LegionWorld {
+        self.flush_commands();
+        self.flush_despawns();
+        self.inner
+    }
+}
+
+/// Wraps an existing `Legion::World`, keeping all of its entities.
+///
+/// Since `fecs::Entity` is `legion::entity::Entity`, entity handles
+/// obtained from the legion world remain valid.
+impl From<LegionWorld> for World {
+    fn from(inner: LegionWorld) -> Self {
+        World {
+            inner,
+            ..World::default()
+        }
+    }
+}
+
+impl From<World> for LegionWorld {
+    fn from(world: World) -> Self {
+        world.into_inner()
+    }
+}
+
+impl AsRef<LegionWorld> for World {
+    fn as_ref(&self) -> &LegionWorld {
+        &self.inner
+    }
+}
+
+impl AsMut<LegionWorld> for World {
+    fn as_mut(&mut self) -> &mut LegionWorld {
+        &mut self.inner
+    }
 }
@@ -1,4 +1,4 @@
-use fecs::{EntityBuilder, World};
+use fecs::{Bundle, EntityBuilder, World};
 
 #[test]
 fn build() {
@@ -54,3 +54,44 @@ fn duplicate_components() {
 
     assert_eq!(*world.get::<i32>(entity), 11);
 }
+
+#[test]
+fn bundles() {
+    #[derive(Bundle)]
+    struct Mob {
+        health: u32,
+        name: &'static str,
+    }
+
+    #[derive(Bundle)]
+    struct Velocity(f32, f64);
+
+    let mut world = World::new();
+
+    let entity = EntityBuilder::new()
+        .with_bundle(Mob {
+            health: 20,
+            name: "zombie",
+        })
+        .with_bundle(Velocity(1.0, 2.0))
+        .build()
+        .spawn_in(&mut world);
+    assert_eq!(*world.get::<u32>(entity), 20);
+    assert_eq!(*world.get::<&'static str>(entity), "zombie");
+    assert_eq!(*world.get::<f32>(entity), 1.0);
+    assert_eq!(*world.get::<f64>(entity), 2.0);
+
+    let entities = world.add_batch(vec![
+        Mob {
+            health: 1,
+            name: "creeper",
+        },
+        Mob {
+            health: 2,
+            name: "skeleton",
+        },
+    ]);
+    assert_eq!(entities.len(), 2);
+    assert_eq!(*world.get::<u32>(entities[0]), 1);
+    assert_eq!(*world.get::<&'static str>(entities[1]), "skeleton");
+}
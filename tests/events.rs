@@ -1,6 +1,9 @@
 use fecs::{
-    event_handler, Entity, EntityBuilder, EventHandlers, OwnedResources, ResourcesProvider, World,
+    event_handler, system, CommandBuffer, Entity, EntityBuilder, EventHandlers, EventReader,
+    Events, Executor, OwnedResources, RawEventHandler, ResourcesEnum, ResourcesProvider, Stage,
+    TriggerInfo, World,
 };
+use std::sync::Arc;
 
 #[test]
 fn basic() {
@@ -25,3 +28,637 @@ fn basic() {
     assert_eq!(*world.get::<i32>(entity), 256);
     assert_eq!(*resources.get::<i32>(), 256);
 }
+
+#[test]
+fn queued_events() {
+    struct Damage(u32);
+
+    #[event_handler]
+    fn on_damage(event: &Damage, total: &mut u32) {
+        *total += event.0;
+    }
+
+    #[system]
+    fn deal_damage(commands: &mut CommandBuffer) {
+        commands.queue_event(Damage(5));
+    }
+
+    #[system]
+    fn record(total: &u32, seen: &mut Vec<u32>) {
+        seen.push(*total);
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new().with(0u32).with(Vec::<u32>::new());
+
+    let mut executor = Executor::new()
+        .with(deal_damage)
+        .with_stage(Stage::PostUpdate, record)
+        .with_event_handlers(EventHandlers::new().with(on_damage));
+    executor.set_event_sync_points(&[Stage::Last]);
+    executor.set_up(&mut resources, &mut world);
+
+    executor.execute(&resources, &mut world);
+    executor.execute(&resources, &mut world);
+
+    assert_eq!(*resources.get::<u32>(), 10);
+    assert_eq!(*resources.get::<Vec<u32>>(), vec![0, 5]);
+
+    world.queue_event(Damage(1));
+    assert_eq!(
+        EventHandlers::new()
+            .with(on_damage)
+            .dispatch_queued(&resources, &mut world),
+        1
+    );
+    assert_eq!(*resources.get::<u32>(), 11);
+}
+
+#[test]
+fn unhandled_queued_events() {
+    #[event_handler]
+    fn on_u8(_event: &u8, count: &mut usize) {
+        *count += 1;
+    }
+
+    #[system]
+    fn queue(world: &World) {
+        world.queue_event(0u8);
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(0usize);
+    let handlers = EventHandlers::new().with(on_u8);
+
+    // Nothing would ever dispatch these, so they are dropped.
+    Executor::new().with(queue).execute(&resources, &mut world);
+    assert_eq!(handlers.dispatch_queued(&resources, &mut world), 0);
+}
+
+#[test]
+fn requeueing_handlers() {
+    #[event_handler]
+    fn requeue(event: &u8, world: &mut World) {
+        world.queue_event(*event);
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new();
+    let handlers = EventHandlers::new().with(requeue);
+
+    world.queue_event(0u8);
+    assert_eq!(handlers.dispatch_queued(&resources, &mut world), 256);
+    assert_eq!(handlers.dispatch_queued(&resources, &mut world), 256);
+}
+
+#[test]
+fn batched() {
+    struct BlockChange(u32);
+
+    #[event_handler]
+    fn per_event(event: &BlockChange, calls: &mut Vec<usize>, total: &mut u32) {
+        calls.push(1);
+        *total += event.0;
+    }
+
+    #[event_handler]
+    fn batch(events: &[BlockChange], calls: &mut Vec<usize>) {
+        calls.push(events.len());
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<usize>::new()).with(0u32);
+    let handlers = EventHandlers::new().with(per_event).with(batch);
+
+    let events: Vec<_> = (1..=3).map(BlockChange).collect();
+    handlers.trigger_batched(&resources, &mut world, &events);
+    assert_eq!(*resources.get::<Vec<usize>>(), vec![1, 1, 1, 3]);
+    assert_eq!(*resources.get::<u32>(), 6);
+
+    handlers.trigger(&resources, &mut world, BlockChange(4));
+    assert_eq!(*resources.get::<Vec<usize>>(), vec![1, 1, 1, 3, 1, 1]);
+    assert_eq!(*resources.get::<u32>(), 10);
+}
+
+#[test]
+fn fold() {
+    struct Login(&'static str);
+
+    #[event_handler]
+    fn not_banned(event: &Login) -> bool {
+        event.0 != "griefer"
+    }
+
+    #[event_handler]
+    fn not_full(_event: &Login, online: &u32) -> bool {
+        *online < 2
+    }
+
+    #[event_handler]
+    fn count(_event: &Login, attempts: &mut usize) {
+        *attempts += 1;
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(1u32).with(0usize);
+    let handlers = EventHandlers::new()
+        .with(not_banned)
+        .with(not_full)
+        .with(count);
+
+    let all = |a, b| a && b;
+    assert!(handlers.trigger_fold(&resources, &mut world, Login("player"), true, all));
+    assert!(!handlers.trigger_fold(&resources, &mut world, Login("griefer"), true, all));
+
+    *resources.get_mut::<u32>() = 2;
+    assert!(!handlers.trigger_fold(&resources, &mut world, Login("player"), true, all));
+    assert_eq!(*resources.get::<usize>(), 3);
+}
+
+#[test]
+fn remove_handlers() {
+    #[event_handler]
+    fn add_one(event: &u8, total: &mut u32) {
+        *total += u32::from(*event);
+    }
+
+    #[event_handler]
+    fn add_ten(event: &u8, total: &mut u32) {
+        *total += 10 * u32::from(*event);
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(0u32);
+    let mut handlers = EventHandlers::new();
+    let one = handlers.add(add_one);
+    handlers.add(add_ten);
+
+    handlers.trigger(&resources, &mut world, 1u8);
+    assert_eq!(*resources.get::<u32>(), 11);
+
+    assert!(handlers.remove(one));
+    assert!(!handlers.remove(one));
+    handlers.trigger(&resources, &mut world, 1u8);
+    assert_eq!(*resources.get::<u32>(), 21);
+
+    handlers.clear::<u8>();
+    handlers.trigger(&resources, &mut world, 1u8);
+    assert_eq!(*resources.get::<u32>(), 21);
+}
+
+#[test]
+fn mutable_events() {
+    #[derive(Clone)]
+    struct Chat(String);
+
+    #[event_handler]
+    fn censor(event: &mut Chat) {
+        event.0 = event.0.replace("heck", "****");
+    }
+
+    #[event_handler]
+    fn log(event: &Chat, log: &mut Vec<String>) {
+        log.push(event.0.clone());
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<String>::new());
+    let handlers = EventHandlers::new().with(censor).with(log);
+
+    let mut chat = Chat("what the heck".to_owned());
+    handlers.trigger_mut(&resources, &mut world, &mut chat);
+    assert_eq!(chat.0, "what the ****");
+
+    handlers.trigger(&resources, &mut world, Chat("heck".to_owned()));
+    assert_eq!(
+        *resources.get::<Vec<String>>(),
+        vec!["what the ****".to_owned(), "****".to_owned()]
+    );
+}
+
+#[test]
+fn mutable_handlers_in_batches() {
+    #[derive(Clone)]
+    struct Chat(String);
+
+    #[event_handler]
+    fn censor(event: &mut Chat, censored: &mut Vec<String>) {
+        event.0 = event.0.replace("heck", "****");
+        censored.push(event.0.clone());
+    }
+
+    #[event_handler]
+    fn log(events: &[Chat], log: &mut Vec<usize>) {
+        log.push(events.len());
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new()
+        .with(Vec::<String>::new())
+        .with(Vec::<usize>::new());
+    let handlers = EventHandlers::new().with(censor).with(log);
+
+    let chats = vec![Chat("heck".to_owned()), Chat("hi".to_owned())];
+    handlers.trigger_batched(&resources, &mut world, &chats);
+
+    // Batched events are shared, so changes are made to clones.
+    assert_eq!(
+        *resources.get::<Vec<String>>(),
+        vec!["****".to_owned(), "hi".to_owned()]
+    );
+    assert_eq!(chats[0].0, "heck");
+    assert_eq!(*resources.get::<Vec<usize>>(), vec![2]);
+}
+
+#[test]
+fn event_channel() {
+    let mut events = Events::new();
+    let mut reader = EventReader::new();
+
+    events.send(1);
+    events.writer().send_batch(vec![2, 3]);
+    assert_eq!(
+        reader.read(&events).copied().collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(reader.read(&events).count(), 0);
+
+    let mut late = events.reader();
+    events.update();
+    events.send(4);
+    assert_eq!(reader.read(&events).copied().collect::<Vec<_>>(), vec![4]);
+    assert_eq!(late.read(&events).copied().collect::<Vec<_>>(), vec![4]);
+
+    // Events are dropped after two updates.
+    let mut slow = EventReader::new();
+    events.update();
+    events.update();
+    events.send(5);
+    assert_eq!(slow.read(&events).copied().collect::<Vec<_>>(), vec![5]);
+    assert_eq!(events.drain().collect::<Vec<_>>(), vec![5]);
+    assert!(events.is_empty());
+    assert_eq!(reader.read(&events).count(), 0);
+}
+
+#[test]
+fn metrics() {
+    #[event_handler]
+    fn on_u8(_event: &u8) {}
+
+    #[event_handler]
+    fn on_u16(_event: &u16) {}
+
+    let mut world = World::new();
+    let resources = OwnedResources::new();
+    let handlers = EventHandlers::new().with(on_u8).with(on_u16);
+
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert!(handlers.metrics().is_empty());
+
+    handlers.set_profiling(true);
+    handlers.trigger(&resources, &mut world, 0u8);
+    handlers.trigger(&resources, &mut world, 0u8);
+    handlers.trigger_batched(&resources, &mut world, &[0u16, 1, 2]);
+
+    let mut metrics = handlers.metrics();
+    metrics.sort_by_key(|metrics| metrics.event);
+    let counts: Vec<_> = metrics
+        .iter()
+        .map(|metrics| (metrics.event, metrics.triggers, metrics.events))
+        .collect();
+    assert_eq!(counts, vec![("u16", 1, 3), ("u8", 2, 2)]);
+
+    handlers.reset_metrics();
+    assert!(handlers.metrics().is_empty());
+}
+
+#[test]
+fn child_handlers() {
+    #[event_handler]
+    fn global(_event: &u8, log: &mut Vec<&'static str>) {
+        log.push("global");
+    }
+
+    #[event_handler]
+    fn local(_event: &u8, log: &mut Vec<&'static str>) {
+        log.push("local");
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    let parent = Arc::new(EventHandlers::new().with(global));
+    let mut child = EventHandlers::child_of(Arc::clone(&parent));
+    child.add(local);
+
+    child.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<Vec<&str>>(), vec!["global", "local"]);
+
+    parent.trigger(&resources, &mut world, 0u8);
+    assert_eq!(
+        *resources.get::<Vec<&str>>(),
+        vec!["global", "local", "global"]
+    );
+
+    child.shadow::<u8>();
+    resources.get_mut::<Vec<&str>>().clear();
+    child.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<Vec<&str>>(), vec!["local"]);
+}
+
+#[test]
+fn filtered() {
+    #[derive(Clone)]
+    struct Damage {
+        kind: &'static str,
+        amount: u32,
+    }
+
+    #[event_handler]
+    fn on_fire(event: &Damage, total: &mut u32) {
+        *total += event.amount;
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(0u32);
+    let mut handlers = EventHandlers::new();
+    handlers.add_filtered(on_fire, |event: &Damage| event.kind == "fire");
+
+    handlers.trigger(
+        &resources,
+        &mut world,
+        Damage {
+            kind: "fire",
+            amount: 2,
+        },
+    );
+    handlers.trigger(
+        &resources,
+        &mut world,
+        Damage {
+            kind: "fall",
+            amount: 5,
+        },
+    );
+    assert_eq!(*resources.get::<u32>(), 2);
+
+    let batch = [
+        Damage {
+            kind: "fall",
+            amount: 1,
+        },
+        Damage {
+            kind: "fire",
+            amount: 3,
+        },
+    ];
+    handlers.trigger_batched(&resources, &mut world, &batch);
+    assert_eq!(*resources.get::<u32>(), 5);
+}
+
+#[test]
+fn filtered_batches() {
+    #[event_handler]
+    fn batch(events: &[u32], batches: &mut Vec<Vec<u32>>) {
+        batches.push(events.to_vec());
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<Vec<u32>>::new());
+    let mut handlers = EventHandlers::new();
+    handlers.add_filtered(batch, |event: &u32| event % 2 == 0);
+
+    handlers.trigger_batched(&resources, &mut world, &[1, 2, 3, 4]);
+    handlers.trigger_batched(&resources, &mut world, &[6, 8]);
+    handlers.trigger_batched(&resources, &mut world, &[5]);
+    assert_eq!(
+        *resources.get::<Vec<Vec<u32>>>(),
+        vec![vec![2, 4], vec![6, 8]]
+    );
+}
+
+#[test]
+fn handler_setup() {
+    struct Greeting(&'static str);
+
+    fn init_greetings(resources: &mut OwnedResources, _world: &mut World) {
+        resources.insert(Greeting("hello"));
+    }
+
+    #[event_handler(setup = "init_greetings")]
+    fn greet(_event: &u8, greeting: &Greeting, #[default] count: &mut usize) {
+        *count += greeting.0.len();
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new();
+    let mut handlers = EventHandlers::new().with(greet);
+    handlers.set_up(&mut resources, &mut world);
+
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<usize>(), 5);
+}
+
+#[test]
+fn queued_events_keep_order_across_types() {
+    struct BlockChange(u32);
+    struct Explosion(u32);
+
+    #[event_handler]
+    fn on_block_change(event: &BlockChange, log: &mut Vec<String>) {
+        log.push(format!("block {}", event.0));
+    }
+
+    #[event_handler]
+    fn on_explosion(event: &Explosion, log: &mut Vec<String>) {
+        log.push(format!("explosion {}", event.0));
+    }
+
+    #[system]
+    fn place(world: &World) {
+        world.queue_event(BlockChange(1));
+        world.queue_event(Explosion(1));
+    }
+
+    #[system]
+    fn explode(world: &World, commands: &mut CommandBuffer) {
+        world.queue_event(Explosion(2));
+        commands.queue_event(BlockChange(2));
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<String>::new());
+    let executor = Executor::new()
+        .with(place)
+        .with(explode)
+        .with_event_handlers(
+            EventHandlers::new()
+                .with(on_explosion)
+                .with(on_block_change),
+        );
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(
+        *resources.get::<Vec<String>>(),
+        vec!["block 1", "explosion 1", "explosion 2", "block 2"]
+    );
+}
+
+#[test]
+fn handler_groups() {
+    #[event_handler]
+    fn award(_event: &u8, awards: &mut u32) {
+        *awards += 1;
+    }
+
+    #[event_handler]
+    fn count(_event: &u8, count: &mut usize) {
+        *count += 1;
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(0u32).with(0usize);
+    let mut handlers = EventHandlers::new().with(count);
+    handlers.add_to_group("achievements", award);
+
+    assert!(handlers.set_group_enabled("achievements", false));
+    assert!(!handlers.set_group_enabled("unknown", false));
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<u32>(), 0);
+    assert_eq!(*resources.get::<usize>(), 1);
+
+    handlers.set_group_enabled("achievements", true);
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<u32>(), 1);
+    assert_eq!(*resources.get::<usize>(), 2);
+}
+
+#[test]
+fn one_shot_handlers() {
+    #[event_handler]
+    fn on_tick(_event: &u8, count: &mut usize) {
+        *count += 1;
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(0usize);
+    let mut handlers = EventHandlers::new();
+    let id = handlers.add_once(on_tick);
+
+    handlers.trigger(&resources, &mut world, 0u8);
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<usize>(), 1);
+    assert!(!handlers.remove(id));
+
+    let id = handlers.add_once(on_tick);
+    assert!(handlers.remove(id));
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(*resources.get::<usize>(), 1);
+}
+
+#[test]
+fn spent_handlers_are_dropped() {
+    struct Holder(Arc<()>);
+
+    impl RawEventHandler for Holder {
+        type Event = u8;
+        type Output = ();
+
+        fn handle(&self, _resources: &ResourcesEnum, _world: &mut World, _event: &u8) {}
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new();
+    let mut handlers = EventHandlers::new();
+    let held = Arc::new(());
+    handlers.add_once(Holder(Arc::clone(&held)));
+
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(Arc::strong_count(&held), 2);
+
+    handlers.set_up(&mut resources, &mut world);
+    assert_eq!(Arc::strong_count(&held), 1);
+}
+
+#[test]
+fn trigger_info() {
+    #[event_handler]
+    fn on_u8(_event: &u8) {}
+
+    let mut world = World::new();
+    let resources = OwnedResources::new();
+    let handlers = EventHandlers::new().with(on_u8).with(on_u8);
+
+    let info = handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(info.handlers, 2);
+    assert!(info.handled());
+
+    let info = handlers.trigger(&resources, &mut world, 0u16);
+    assert_eq!(info, TriggerInfo::default());
+    assert!(!info.handled());
+
+    // Handlers rejecting the event through their filter are not counted.
+    let mut handlers = EventHandlers::new();
+    handlers.add_filtered(on_u8, |event: &u8| *event > 0);
+    assert!(!handlers.trigger(&resources, &mut world, 0u8).handled());
+    assert_eq!(handlers.trigger(&resources, &mut world, 1u8).handlers, 1);
+}
+
+#[test]
+fn sticky_events() {
+    #[derive(Clone)]
+    struct WorldLoaded(&'static str);
+
+    #[event_handler]
+    fn on_loaded(event: &WorldLoaded, log: &mut Vec<&'static str>) {
+        log.push(event.0);
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    let mut handlers = EventHandlers::new();
+
+    let info = handlers.trigger_sticky(&resources, &mut world, WorldLoaded("overworld"));
+    assert!(!info.handled());
+
+    handlers.add_and_replay(&resources, &mut world, on_loaded);
+    assert_eq!(*resources.get::<Vec<&str>>(), vec!["overworld"]);
+
+    handlers.trigger_sticky(&resources, &mut world, WorldLoaded("nether"));
+    assert_eq!(*resources.get::<Vec<&str>>(), vec!["overworld", "nether"]);
+
+    handlers.clear_sticky::<WorldLoaded>();
+    handlers.add_and_replay(&resources, &mut world, on_loaded);
+    assert_eq!(*resources.get::<Vec<&str>>(), vec!["overworld", "nether"]);
+}
+
+#[test]
+fn priorities() {
+    #[event_handler]
+    fn normal(_event: &u8, log: &mut Vec<&'static str>) {
+        log.push("normal");
+    }
+
+    #[event_handler(priority = 100)]
+    fn first(_event: &u8, log: &mut Vec<&'static str>) {
+        log.push("first");
+    }
+
+    #[event_handler(priority = 10)]
+    fn second(_event: &u8, log: &mut Vec<&'static str>) {
+        log.push("second");
+    }
+
+    let mut world = World::new();
+    let resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    let handlers = EventHandlers::new()
+        .with(normal)
+        .with(second)
+        .with(first)
+        .with(second);
+
+    handlers.trigger(&resources, &mut world, 0u8);
+    assert_eq!(
+        *resources.get::<Vec<&str>>(),
+        vec!["first", "second", "second", "normal"]
+    );
+}
@@ -0,0 +1,315 @@
+use fecs::{
+    tag_value, ComponentTypeId, DynamicQuery, EntityBuilder, Query, QuerySingleError, World,
+};
+use std::ops::ControlFlow;
+
+#[test]
+fn single() {
+    let mut world = World::new();
+    EntityBuilder::new()
+        .with(10i32)
+        .build()
+        .spawn_in(&mut world);
+    EntityBuilder::new()
+        .with(1i32)
+        .with(5u64)
+        .build()
+        .spawn_in(&mut world);
+
+    assert_eq!(*world.query::<&u64>().single(), 5);
+    *world.query::<&mut u64>().single_mut() += 1;
+    assert_eq!(*world.query::<&u64>().single(), 6);
+
+    assert!(matches!(
+        world.query::<&i32>().try_single(),
+        Err(QuerySingleError::MultipleEntities)
+    ));
+    assert!(matches!(
+        world.query::<&u128>().try_single_mut(),
+        Err(QuerySingleError::NoEntities)
+    ));
+}
+
+#[test]
+fn large_tuple() {
+    let mut world = World::new();
+    let entity = EntityBuilder::new()
+        .with(1u8)
+        .with(2u16)
+        .with(3u32)
+        .with(4u64)
+        .with(5u128)
+        .with(6i8)
+        .with(7i16)
+        .with(8i32)
+        .with(9i64)
+        .with(10i128)
+        .with(false)
+        .build()
+        .spawn_in(&mut world);
+
+    for (a, b, c, d, e, f, g, h, i, j, mut k) in world
+        .query::<(
+            &u8,
+            &u16,
+            &u32,
+            &u64,
+            &u128,
+            &i8,
+            &i16,
+            &i32,
+            &i64,
+            &i128,
+            &mut bool,
+        )>()
+        .iter_mut()
+    {
+        let sum = *a as i128
+            + *b as i128
+            + *c as i128
+            + *d as i128
+            + *e as i128
+            + *f as i128
+            + *g as i128
+            + *h as i128
+            + *i as i128
+            + *j;
+        *k = sum == 55;
+    }
+
+    assert!(*world.get::<bool>(entity));
+}
+
+#[test]
+fn iter_combinations() {
+    let mut world = World::new();
+    for x in 1..=4i32 {
+        EntityBuilder::new().with(x).build().spawn_in(&mut world);
+    }
+
+    let mut query = world.query::<&mut i32>();
+    let mut combinations = query.iter_combinations::<2>();
+
+    let mut count = 0;
+    let mut sum = 0;
+    while let Some([a, b]) = combinations.fetch_next() {
+        assert_ne!(**a, **b);
+        count += 1;
+        sum += **a * **b;
+    }
+
+    assert_eq!(count, 6);
+    assert_eq!(sum, 35);
+    assert!(combinations.fetch_next().is_none());
+
+    drop(combinations);
+    assert!(query.iter_combinations::<5>().fetch_next().is_none());
+}
+
+#[test]
+fn try_for_each() {
+    let mut world = World::new();
+    for x in 0..100i32 {
+        EntityBuilder::new().with(x).build().spawn_in(&mut world);
+    }
+
+    let mut visited = 0;
+    let found = world.query::<&i32>().try_for_each(|x| {
+        visited += 1;
+        if *x == 10 {
+            ControlFlow::Break(*x)
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert_eq!(found, ControlFlow::Break(10));
+    assert!(visited < 100);
+
+    let result =
+        world
+            .query::<&i32>()
+            .try_for_each_fallible(|x| if *x < 50 { Ok(()) } else { Err(*x) });
+    assert!(result.is_err());
+}
+
+#[test]
+fn count() {
+    let mut world = World::new();
+    for x in 0..10i32 {
+        EntityBuilder::new().with(x).build().spawn_in(&mut world);
+    }
+    for x in 0..5i32 {
+        EntityBuilder::new()
+            .with(x)
+            .with(x as u64)
+            .build()
+            .spawn_in(&mut world);
+    }
+
+    assert_eq!(world.query::<&i32>().count(), 15);
+    assert_eq!(world.query::<(&i32, &u64)>().count(), 5);
+    assert_eq!(world.query::<&u128>().count(), 0);
+}
+
+#[test]
+fn dynamic_query() {
+    let mut world = World::new();
+    let entity = EntityBuilder::new()
+        .with(10i32)
+        .with(2u64)
+        .build()
+        .spawn_in(&mut world);
+    EntityBuilder::new().with(5i32).build().spawn_in(&mut world);
+
+    let query = DynamicQuery::new()
+        .read(ComponentTypeId::of::<u64>())
+        .write(ComponentTypeId::of::<i32>());
+
+    let mut matched = 0;
+    query.for_each(&mut world, |e, components| {
+        matched += 1;
+        assert_eq!(e, entity);
+        assert_eq!(components.len(), 2);
+        assert!(!components[0].is_mutable());
+        assert!(components[1].is_mutable());
+
+        let factor = unsafe { *components[0].ptr().cast::<u64>().as_ptr() };
+        unsafe { *components[1].ptr().cast::<i32>().as_ptr() *= factor as i32 };
+    });
+
+    assert_eq!(matched, 1);
+    assert_eq!(*world.get::<i32>(entity), 20);
+}
+
+#[test]
+fn collect_into() {
+    let mut world = World::new();
+    for x in 0..4i32 {
+        EntityBuilder::new()
+            .with(x)
+            .with(format!("entity {}", x))
+            .build()
+            .spawn_in(&mut world);
+    }
+
+    let mut buffer = Vec::new();
+    world.query::<(&i32, &String)>().collect_into(&mut buffer);
+    assert_eq!(buffer.len(), 4);
+    for (entity, (x, name)) in &buffer {
+        assert_eq!(*world.get::<i32>(*entity), *x);
+        assert_eq!(name, &format!("entity {}", x));
+    }
+
+    let capacity = buffer.capacity();
+    world.query::<(&i32, &String)>().collect_into(&mut buffer);
+    assert_eq!(buffer.len(), 4);
+    assert_eq!(buffer.capacity(), capacity);
+}
+
+#[test]
+fn filter_tag_value() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Biome {
+        Desert,
+        Forest,
+    }
+
+    let mut world = World::new();
+    world.spawn_tagged((Biome::Desert,), vec![(1i32,), (2i32,)]);
+    let forest = world.spawn_tagged((Biome::Forest,), vec![(4i32,)])[0];
+
+    let desert_sum: i32 = world
+        .query::<&i32>()
+        .filter(tag_value(&Biome::Desert))
+        .iter_mut()
+        .map(|x| *x)
+        .sum();
+    assert_eq!(desert_sum, 3);
+
+    assert_eq!(world.get_tag::<Biome>(forest), Some(&Biome::Forest));
+}
+
+#[test]
+fn access() {
+    let access = <(&i32, &mut u64) as Query>::access();
+    assert_eq!(access.reads, vec![ComponentTypeId::of::<i32>()]);
+    assert_eq!(access.writes, vec![ComponentTypeId::of::<u64>()]);
+
+    assert!(access.conflicts_with(&<&u64 as Query>::access()));
+    assert!(access.conflicts_with(&<&mut i32 as Query>::access()));
+    assert!(!access.conflicts_with(&<(&i32, &u128) as Query>::access()));
+}
+
+#[test]
+fn indexed_chunks() {
+    let mut world = World::new();
+    for x in 0..3i32 {
+        EntityBuilder::new().with(x).build().spawn_in(&mut world);
+    }
+    for x in 3..5i32 {
+        EntityBuilder::new()
+            .with(x)
+            .with(x as u64)
+            .build()
+            .spawn_in(&mut world);
+    }
+
+    let mut query = world.query::<&mut i32>();
+    let chunk_count = query.chunk_count();
+    assert_eq!(chunk_count, 2);
+
+    let mut total = 0;
+    for index in 0..chunk_count {
+        let mut chunk = query.get_chunk(index).unwrap();
+        for mut x in chunk.iter_mut() {
+            *x += 1;
+            total += *x;
+        }
+    }
+    assert_eq!(total, 15);
+    assert!(query.get_chunk(chunk_count).is_none());
+
+    let lengths: Vec<_> = query
+        .iter_chunks_mut()
+        .map(|chunk| chunk.entities().len())
+        .collect();
+    assert_eq!(lengths, vec![3, 2]);
+}
+
+#[test]
+fn with_without() {
+    struct Frozen;
+
+    let mut world = World::new();
+    for x in 0..4i32 {
+        let mut builder = EntityBuilder::new().with(x);
+        if x % 2 == 0 {
+            builder.add(Frozen);
+        }
+        builder.build().spawn_in(&mut world);
+    }
+
+    world
+        .query::<&mut i32>()
+        .without::<Frozen>()
+        .iter_mut()
+        .for_each(|mut x| *x += 10);
+
+    let mut frozen: Vec<i32> = world
+        .query::<&i32>()
+        .with::<Frozen>()
+        .iter_mut()
+        .map(|x| *x)
+        .collect();
+    frozen.sort();
+    assert_eq!(frozen, vec![0, 2]);
+
+    let mut thawed: Vec<i32> = world
+        .query::<&i32>()
+        .filter(fecs::without::<Frozen>())
+        .iter_mut()
+        .map(|x| *x)
+        .collect();
+    thawed.sort();
+    assert_eq!(thawed, vec![11, 13]);
+}
@@ -1,4 +1,5 @@
-use fecs::{OwnedResources, RefResources, ResourcesProvider};
+use fecs::{HasherKind, OwnedResources, RefResources, ResourcesProvider, WorldOptions};
+use std::sync::Arc;
 
 #[test]
 fn resources() {
@@ -51,3 +52,414 @@ fn refs() {
     drop(resources);
     assert_eq!(r, "test");
 }
+
+#[test]
+fn configured_hasher() {
+    for hasher in &[HasherKind::Fx, HasherKind::SeededFx(42), HasherKind::Random] {
+        let options = WorldOptions::new().hasher(*hasher);
+        let resources = OwnedResources::with_options(&options)
+            .with(10i32)
+            .with(15u64);
+
+        assert_eq!(*resources.get::<i32>(), 10);
+        assert_eq!(*resources.get::<u64>(), 15);
+    }
+}
+
+#[test]
+fn remove() {
+    let mut resources = OwnedResources::new()
+        .with(10i32)
+        .with(String::from("match"));
+
+    assert_eq!(resources.remove::<String>(), Some(String::from("match")));
+    assert_eq!(resources.remove::<String>(), None);
+    assert!(!resources.contains::<String>());
+    assert!(resources.contains::<i32>());
+
+    resources.clear();
+    assert!(resources.try_get::<i32>().is_err());
+}
+
+#[test]
+fn get_or_insert_with() {
+    let mut resources = OwnedResources::new();
+
+    *resources.get_or_insert_with(|| 10i32) += 1;
+    *resources.get_or_insert_with(|| 100i32) += 1;
+    assert_eq!(*resources.get::<i32>(), 12);
+
+    // The borrow from `get_or_insert_with` was released.
+    assert!(resources.try_get_mut::<i32>().is_ok());
+}
+
+#[test]
+fn change_detection() {
+    let resources = OwnedResources::new().with(10i32).with(15u64);
+
+    assert!(resources.is_changed::<i32>());
+    resources.clear_changed();
+    assert!(!resources.is_changed::<i32>());
+
+    let _ = *resources.get::<i32>();
+    assert!(!resources.is_changed::<i32>());
+
+    *resources.get_mut::<i32>() += 1;
+    assert!(resources.is_changed::<i32>());
+    assert!(!resources.is_changed::<u64>());
+    assert!(!resources.is_changed::<u128>());
+}
+
+#[test]
+fn many_refs() {
+    let resources = OwnedResources::new();
+
+    let (mut a, mut b, mut c, mut d, mut e, mut f) = (1u8, 2u16, 3u32, 4u64, 5i8, 6i16);
+    let resources = RefResources::new(&resources, (&mut a, &mut b, &mut c, &mut d, &mut e, &mut f));
+
+    assert_eq!(*resources.get::<u8>(), 1);
+    assert_eq!(*resources.get::<i16>(), 6);
+    *resources.get_mut::<i8>() = 50;
+
+    drop(resources);
+    assert_eq!(e, 50);
+}
+
+#[test]
+fn arc_resources() {
+    let mut resources = OwnedResources::new();
+    resources.insert_arc(Arc::new(String::from("assets")));
+
+    let assets = resources.get_arc::<String>();
+    let _guard = resources.get_arc::<String>();
+    assert_eq!(*assets, "assets");
+    assert_eq!(Arc::strong_count(&assets), 3);
+
+    // Shared resources are separate from borrowed ones.
+    assert!(resources.try_get::<String>().is_err());
+
+    assert!(resources.remove_arc::<String>().is_some());
+    assert!(resources.try_get_arc::<String>().is_err());
+    assert_eq!(*assets, "assets");
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn serialize() {
+    use fecs::ResourceRegistry;
+
+    let registry = ResourceRegistry::new()
+        .with::<i32>("score")
+        .with::<String>("level_name");
+
+    let resources = OwnedResources::new()
+        .with(10i32)
+        .with(String::from("overworld"))
+        .with(15u64);
+
+    let json = serde_json::to_string(&resources.serialize(&registry)).unwrap();
+
+    let mut loaded = OwnedResources::new().with(0i32);
+    let mut deserializer = serde_json::Deserializer::from_str(&json);
+    loaded
+        .deserialize_into(&registry, &mut deserializer)
+        .unwrap();
+
+    assert_eq!(*loaded.get::<i32>(), 10);
+    assert_eq!(*loaded.get::<String>(), "overworld");
+    assert!(loaded.try_get::<u64>().is_err());
+}
+
+#[test]
+fn keyed() {
+    struct ChunkMap(u32);
+
+    let mut resources = OwnedResources::new();
+    resources.insert_keyed("overworld", ChunkMap(0));
+    resources.insert_keyed("nether", ChunkMap(1));
+
+    {
+        // Distinct keys are borrowed independently.
+        let mut overworld = resources.get_keyed_mut::<ChunkMap>("overworld");
+        let nether = resources.get_keyed::<ChunkMap>("nether");
+        overworld.0 += nether.0 + 10;
+    }
+
+    assert_eq!(resources.get_keyed::<ChunkMap>("overworld").0, 11);
+    assert!(resources.try_get_keyed::<ChunkMap>("end").is_err());
+    assert!(resources.try_get::<ChunkMap>().is_err());
+
+    let mut keys: Vec<_> = resources.keys::<ChunkMap>().collect();
+    keys.sort();
+    assert_eq!(keys, vec!["nether", "overworld"]);
+
+    assert_eq!(
+        resources
+            .remove_keyed::<ChunkMap>("nether")
+            .map(|map| map.0),
+        Some(1)
+    );
+}
+
+#[test]
+fn scope() {
+    let mut resources = OwnedResources::new().with(10i32);
+
+    let seen = resources.scope(20i32, |resources| *resources.get::<i32>());
+    assert_eq!(seen, 20);
+    assert_eq!(*resources.get::<i32>(), 10);
+
+    resources.scope(5u64, |resources| {
+        *resources.get_mut::<u64>() += 1;
+        assert_eq!(*resources.get::<i32>(), 10);
+    });
+    assert!(resources.try_get::<u64>().is_err());
+}
+
+#[test]
+fn get_many() {
+    let resources = OwnedResources::new()
+        .with(10i32)
+        .with(15u64)
+        .with(String::from("log"));
+
+    {
+        let (a, b, mut c) = resources.get_many::<(&i32, &u64, &mut String)>();
+        c.push_str(&format!(" {} {}", *a, *b));
+    }
+    assert_eq!(*resources.get::<String>(), "log 10 15");
+
+    // A failed fetch releases the borrows it already obtained.
+    assert!(resources.try_get_many::<(&mut i32, &u128)>().is_err());
+    assert!(resources.try_get_mut::<i32>().is_ok());
+
+    assert!(resources.try_get_many::<(&mut i32, &i32)>().is_err());
+}
+
+#[test]
+fn lazy() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+
+    let mut resources = OwnedResources::new();
+    resources.register_lazy(|| {
+        CONSTRUCTED.fetch_add(1, Ordering::SeqCst);
+        vec![1u32, 2, 3]
+    });
+
+    assert!(resources.contains::<Vec<u32>>());
+    assert_eq!(CONSTRUCTED.load(Ordering::SeqCst), 0);
+
+    resources.get_mut::<Vec<u32>>().push(4);
+    assert_eq!(resources.get::<Vec<u32>>().len(), 4);
+    assert_eq!(CONSTRUCTED.load(Ordering::SeqCst), 1);
+
+    assert_eq!(resources.remove::<Vec<u32>>(), Some(vec![1, 2, 3, 4]));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn get_async() {
+    use fecs::ResourceError;
+    use futures::task::noop_waker_ref;
+    use std::future::Future;
+    use std::task::{Context, Poll};
+
+    let mut resources = OwnedResources::new();
+    resources.insert(10i32);
+
+    let mut cx = Context::from_waker(noop_waker_ref());
+
+    let guard = resources.get_mut::<i32>();
+    let mut future = Box::pin(resources.get_async::<i32>());
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+
+    drop(guard);
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(Ok(value)) => assert_eq!(*value, 10),
+        _ => panic!("borrow not obtained after release"),
+    }
+
+    let mut missing = Box::pin(resources.get_mut_async::<u64>());
+    assert!(matches!(
+        missing.as_mut().poll(&mut cx),
+        Poll::Ready(Err(ResourceError::NotFound(_)))
+    ));
+}
+
+#[test]
+fn borrow_conflict_location() {
+    let mut resources = OwnedResources::new();
+    resources.insert(10i32);
+
+    let line = line!() + 1;
+    let guard = resources.get_mut::<i32>();
+
+    assert!(matches!(
+        resources.try_get::<i32>(),
+        Err(fecs::ResourceError::AlreadyBorrowed)
+    ));
+    if cfg!(debug_assertions) {
+        let location = resources.borrowed_at::<i32>().unwrap();
+        assert_eq!(location.file(), file!());
+        assert_eq!(location.line(), line);
+    }
+
+    drop(guard);
+    assert!(resources.borrowed_at::<i32>().is_none());
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "resource i32 already borrowed at tests/resources.rs:")]
+fn borrow_conflict_panic_location() {
+    let resources = OwnedResources::new().with(10i32);
+
+    let _guard = resources.get_mut::<i32>();
+    let _ = resources.get::<i32>();
+}
+
+#[test]
+fn get_or_default() {
+    let mut resources = OwnedResources::new();
+
+    *resources.get_or_default::<u32>() += 1;
+    *resources.get_or_default::<u32>() += 1;
+
+    assert_eq!(*resources.get::<u32>(), 2);
+}
+
+#[test]
+fn extend() {
+    let mut resources = OwnedResources::new().with(1u32).with(String::from("app"));
+    let mut plugin = OwnedResources::new().with(2u32).with(0.5f32);
+    plugin.insert_keyed("plugin", 3u8);
+
+    resources.extend(plugin);
+
+    assert_eq!(*resources.get::<u32>(), 2);
+    assert_eq!(*resources.get::<f32>(), 0.5);
+    assert_eq!(resources.get::<String>().as_str(), "app");
+    assert_eq!(*resources.get_keyed::<u8>("plugin"), 3);
+}
+
+#[test]
+fn insert_shared() {
+    let mut resources = OwnedResources::new().with(1u32);
+
+    {
+        let value = resources.get::<u32>();
+        assert!(resources.insert_shared(String::from("plugin")).is_ok());
+        assert_eq!(resources.insert_shared(2u32), Err(2));
+        assert_eq!(*value, 1);
+    }
+
+    assert_eq!(resources.get::<String>().as_str(), "plugin");
+    assert!(resources.contains::<String>());
+    assert_eq!(resources.remove::<String>(), Some(String::from("plugin")));
+}
+
+#[test]
+fn bundle() {
+    let mut resources = OwnedResources::new().with_bundle((1u32, String::from("a")));
+    resources.insert_bundle((2u32, 0.5f32, 3u8));
+
+    assert_eq!(*resources.get::<u32>(), 2);
+    assert_eq!(resources.get::<String>().as_str(), "a");
+    assert_eq!(*resources.get::<f32>(), 0.5);
+    assert_eq!(*resources.get::<u8>(), 3);
+}
+
+#[test]
+fn hooks() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static INSERTED: AtomicUsize = AtomicUsize::new(0);
+    static REMOVED: AtomicUsize = AtomicUsize::new(0);
+
+    let mut resources = OwnedResources::new();
+    resources.on_insert::<Vec<u32>>(|value| {
+        value.push(0);
+        INSERTED.fetch_add(1, Ordering::SeqCst);
+    });
+    resources.on_remove::<Vec<u32>>(|_| {
+        REMOVED.fetch_add(1, Ordering::SeqCst);
+    });
+
+    resources.insert(vec![1u32]);
+    assert_eq!(*resources.get::<Vec<u32>>(), vec![1, 0]);
+
+    resources.insert(vec![2u32]);
+    assert_eq!(INSERTED.load(Ordering::SeqCst), 2);
+    assert_eq!(REMOVED.load(Ordering::SeqCst), 1);
+
+    resources.clear();
+    assert_eq!(REMOVED.load(Ordering::SeqCst), 2);
+
+    resources.insert(vec![3u32]);
+    drop(resources);
+    assert_eq!(REMOVED.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn resource_id() {
+    let mut resources = OwnedResources::new();
+    let id = resources.resource_id::<u32>();
+
+    assert!(resources.try_get_by_id(id).is_err());
+
+    resources.insert(5u32);
+    *resources.get_mut_by_id(id) += 1;
+    assert_eq!(*resources.get_by_id(id), 6);
+
+    // Handles from another container fall back to a normal lookup.
+    let other = OwnedResources::new().with(String::new()).with(1u32);
+    assert_eq!(*other.get_by_id(id), 1);
+}
+
+#[test]
+fn snapshot() {
+    let mut resources = OwnedResources::new().with(String::from("not cloned"));
+    resources.insert_cloneable(vec![1u32]);
+
+    let snapshot = resources.snapshot().unwrap();
+    assert!(!snapshot.contains::<String>());
+
+    resources.get_mut::<Vec<u32>>().push(2);
+    resources.extend(snapshot);
+
+    assert_eq!(*resources.get::<Vec<u32>>(), vec![1]);
+    assert_eq!(resources.get::<String>().as_str(), "not cloned");
+}
+
+#[test]
+fn queue_remove() {
+    let mut resources = OwnedResources::new().with(1u32).with(2u64);
+
+    {
+        let _value = resources.get::<u32>();
+        resources.queue_remove::<u32>();
+        assert!(resources.contains::<u32>());
+    }
+
+    resources.apply_queued();
+    assert!(!resources.contains::<u32>());
+    assert!(resources.contains::<u64>());
+
+    resources.as_resources_ref().queue_remove::<u64>();
+    assert!(resources.contains::<u64>());
+    resources.apply_queued();
+    assert!(!resources.contains::<u64>());
+}
+
+#[test]
+fn freeze() {
+    let mut resources = OwnedResources::new().with(1u32).with(String::from("a"));
+
+    let frozen = resources.freeze();
+    let (a, b) = (frozen.get::<u32>(), frozen.get::<u32>());
+    assert_eq!(*a + *b, 2);
+    assert_eq!(frozen.get::<String>().as_str(), "a");
+    assert!(frozen.try_get::<u64>().is_err());
+}
@@ -1,4 +1,12 @@
-use fecs::{system, EntityBuilder, Executor, OwnedResources, ResourcesProvider, World};
+use fecs::{
+    system, AsyncSystem, BoxFuture, CommandBuffer, EntityBuilder, ErrorPolicy, Executor,
+    ExecutorBuilder, FixedTimestep, OwnedResources, RawSystem, ResourcesEnum, ResourcesProvider,
+    ScheduleError, Stage, SubExecutor, SystemAccess, SystemGroup, ThreadLocalResources, Tick, Time,
+    Watchdog, World, WorldAccess,
+};
+use std::any::TypeId;
+use std::cell::Cell;
+use std::rc::Rc;
 
 #[test]
 fn basic() {
@@ -58,3 +66,1171 @@ fn default_resource() {
     executor.execute(&resources, &mut world);
     assert_eq!(*resources.get::<i32>(), 1);
 }
+
+#[test]
+fn thread_local() {
+    struct ThreadLocalSystem;
+
+    impl RawSystem for ThreadLocalSystem {
+        fn run(&self, _resources: &ResourcesEnum, _world: &mut World, _executor: &Executor) {
+            unreachable!()
+        }
+
+        fn set_up(&mut self, _resources: &mut OwnedResources, _world: &mut World) {}
+
+        fn is_thread_local(&self) -> bool {
+            true
+        }
+
+        fn run_thread_local(
+            &self,
+            resources: &ResourcesEnum,
+            thread_local: &ThreadLocalResources,
+            _world: &mut World,
+            _executor: &Executor,
+        ) {
+            let counter = thread_local.get::<Rc<Cell<i32>>>();
+            counter.set(counter.get() + *resources.get::<i32>());
+        }
+    }
+
+    let executor = Executor::new().with(ThreadLocalSystem);
+
+    let counter = Rc::new(Cell::new(0));
+    let thread_local = ThreadLocalResources::new().with(Rc::clone(&counter));
+    let resources = OwnedResources::new().with(5i32);
+
+    executor.execute_thread_local(&resources, &thread_local, &mut World::new());
+    executor.execute_thread_local(&resources, &thread_local, &mut World::new());
+    assert_eq!(counter.get(), 10);
+}
+
+#[test]
+fn access() {
+    #[system]
+    fn test_system(a: &i32, b: &mut u32, world: &World) {
+        let _ = (a, b, world);
+    }
+
+    let access = test_system.access().unwrap();
+    assert_eq!(access.resource_reads, vec![TypeId::of::<i32>()]);
+    assert_eq!(access.resource_writes, vec![TypeId::of::<u32>()]);
+    assert_eq!(access.world, WorldAccess::Read);
+    assert!(!test_system.is_exclusive());
+}
+
+#[test]
+fn exclusive() {
+    #[system]
+    fn exclusive_system(world: &mut World) {
+        let _ = world;
+    }
+
+    #[system]
+    fn shared_system(x: &mut i32) {
+        *x += 1;
+    }
+
+    assert!(exclusive_system.is_exclusive());
+    assert!(!shared_system.is_exclusive());
+
+    let executor = Executor::new().with(exclusive_system).with(shared_system);
+    let exclusive: Vec<_> = executor.systems().map(|info| info.exclusive).collect();
+    assert_eq!(exclusive, vec![true, false]);
+
+    // Systems which only implement `run` are exclusive whatever their access.
+    struct ReadOnly;
+
+    impl RawSystem for ReadOnly {
+        fn run(&self, _resources: &ResourcesEnum, _world: &mut World, _executor: &Executor) {}
+
+        fn set_up(&mut self, _resources: &mut OwnedResources, _world: &mut World) {}
+
+        fn access(&self) -> Option<SystemAccess> {
+            Some(SystemAccess {
+                world: WorldAccess::Read,
+                ..SystemAccess::default()
+            })
+        }
+    }
+
+    assert!(ReadOnly.is_exclusive());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel() {
+    #[system]
+    fn first(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn second(y: &mut u32, world: &World) {
+        let _ = world;
+        *y += 1;
+    }
+
+    #[system]
+    fn third(x: &i32, y: &mut u32) {
+        *y += *x as u32;
+    }
+
+    #[system]
+    fn fourth(world: &mut World) {
+        world.spawn(vec![(0i32,)]);
+    }
+
+    let executor = Executor::new()
+        .with(first)
+        .with(second)
+        .with(third)
+        .with(fourth);
+    let resources = OwnedResources::new().with(0i32).with(0u32);
+    let mut world = World::new();
+
+    executor.execute_parallel(&resources, &mut world);
+    executor.execute_parallel(&resources, &mut world);
+
+    assert_eq!(*resources.get::<i32>(), 2);
+    assert_eq!(*resources.get::<u32>(), 5);
+}
+
+#[test]
+fn stages() {
+    #[system]
+    fn push_update(log: &mut Vec<&'static str>) {
+        log.push("update");
+    }
+
+    #[system]
+    fn push_pre_update(log: &mut Vec<&'static str>) {
+        log.push("pre_update");
+    }
+
+    #[system]
+    fn push_last(log: &mut Vec<&'static str>) {
+        log.push("last");
+    }
+
+    let executor = Executor::new()
+        .with_stage(Stage::Last, push_last)
+        .with(push_update)
+        .with_stage(Stage::PreUpdate, push_pre_update);
+
+    let resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    executor.execute(&resources, &mut World::new());
+
+    assert_eq!(
+        *resources.get::<Vec<&'static str>>(),
+        vec!["pre_update", "update", "last"]
+    );
+}
+
+#[test]
+fn ordering() {
+    #[system(label = "broadcast", after = "simulate")]
+    fn broadcast(log: &mut Vec<&'static str>) {
+        log.push("broadcast");
+    }
+
+    #[system(label = "simulate")]
+    fn simulate(log: &mut Vec<&'static str>) {
+        log.push("simulate");
+    }
+
+    #[system(before = "simulate")]
+    fn input(log: &mut Vec<&'static str>) {
+        log.push("input");
+    }
+
+    let mut executor = Executor::new().with(broadcast).with(simulate).with(input);
+
+    let mut resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    let mut world = World::new();
+    executor.set_up(&mut resources, &mut world);
+    executor.execute(&resources, &mut world);
+
+    assert_eq!(
+        *resources.get::<Vec<&'static str>>(),
+        vec!["input", "simulate", "broadcast"]
+    );
+}
+
+#[test]
+fn ordering_cycle() {
+    #[system(label = "a", after = "b")]
+    fn a() {}
+
+    #[system(label = "b", after = "a")]
+    fn b() {}
+
+    #[system]
+    fn c() {}
+
+    let mut resources = OwnedResources::new();
+    let mut executor = Executor::new().with(a).with(b).with(c);
+    executor.set_up(&mut resources, &mut World::new());
+
+    let names: Vec<_> = executor.systems().map(|info| info.name).collect();
+    assert_eq!(names, vec!["system::c", "system::a", "system::b"]);
+
+    let errors = executor.validate(&resources).unwrap_err();
+    assert!(matches!(
+        errors.as_slice(),
+        [ScheduleError::OrderingCycle { systems }] if systems == &["a", "b"]
+    ));
+}
+
+#[test]
+fn set_enabled() {
+    #[system]
+    fn increment(x: &mut i32) {
+        *x += 1;
+    }
+
+    let executor = Executor::new().with(increment);
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    assert!(executor.set_enabled("increment", false));
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 0);
+
+    assert!(executor.set_enabled("increment", true));
+    assert_eq!(executor.is_enabled("increment"), Some(true));
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 1);
+
+    assert!(!executor.set_enabled("missing", false));
+}
+
+#[test]
+fn timings() {
+    #[system]
+    fn sleep() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    let executor = Executor::new().with(sleep);
+    let resources = OwnedResources::new();
+    let mut world = World::new();
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(executor.timings()[0].1.runs, 0);
+
+    executor.set_profiling(true);
+    executor.execute(&resources, &mut world);
+    executor.execute(&resources, &mut world);
+
+    let (name, timings) = executor.timings()[0];
+    assert_eq!(name, "system::sleep");
+    assert_eq!(timings.runs, 2);
+    assert!(timings.max >= std::time::Duration::from_millis(1));
+    assert!(timings.average <= timings.max);
+}
+
+#[test]
+fn run_criteria() {
+    #[derive(PartialEq)]
+    enum GameState {
+        Loading,
+        Playing,
+    }
+
+    #[system]
+    fn increment(x: &mut i32) {
+        *x += 1;
+    }
+
+    let mut executor = Executor::new();
+    executor.add_with_criteria(increment, |resources| {
+        *resources.get::<GameState>() == GameState::Playing
+    });
+
+    let resources = OwnedResources::new().with(0i32).with(GameState::Loading);
+    let mut world = World::new();
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 0);
+
+    *resources.get_mut::<GameState>() = GameState::Playing;
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 1);
+
+    // Criteria apply only to the added system, not to others of the same name.
+    executor.add(increment);
+    executor.add_with_criteria(increment, |_| false);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 3);
+}
+
+#[test]
+fn fixed_timestep() {
+    use std::time::Duration;
+
+    #[system]
+    fn step(x: &mut i32) {
+        *x += 1;
+    }
+
+    let executor = Executor::new().with(FixedTimestep::new(Duration::from_millis(20)).with(step));
+    let resources = OwnedResources::new().with(0i32).with(Time::new());
+    let mut world = World::new();
+
+    for (delta, expected) in [(10, 0), (15, 1), (50, 3), (0, 3)].iter() {
+        resources
+            .get_mut::<Time>()
+            .advance(Duration::from_millis(*delta));
+        executor.execute(&resources, &mut world);
+        assert_eq!(*resources.get::<i32>(), *expected);
+    }
+
+    // Time beyond `max_steps` is dropped rather than caught up on.
+    let executor = Executor::new().with(
+        FixedTimestep::new(Duration::from_millis(20))
+            .with_max_steps(2)
+            .with(step),
+    );
+    for (delta, expected) in [(100, 5), (0, 5), (30, 6)].iter() {
+        resources
+            .get_mut::<Time>()
+            .advance(Duration::from_millis(*delta));
+        executor.execute(&resources, &mut world);
+        assert_eq!(*resources.get::<i32>(), *expected);
+    }
+}
+
+#[test]
+fn error_policy() {
+    #[system]
+    fn fail(x: &mut i32) -> Result<(), std::num::ParseIntError> {
+        *x += 1;
+        "not a number".parse::<i32>()?;
+        *x += 100;
+        Ok(())
+    }
+
+    #[system]
+    fn after_fail(x: &mut i32) {
+        *x += 10;
+    }
+
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    let executor = Executor::new().with(fail).with(after_fail);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 11);
+
+    let executor = Executor::new()
+        .with_error_policy(ErrorPolicy::AbortTick)
+        .with(fail)
+        .with(after_fail);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 12);
+
+    executor.execute_thread_local(&resources, &ThreadLocalResources::new(), &mut world);
+    assert_eq!(*resources.get::<i32>(), 13);
+
+    // Run directly, errors are logged.
+    fail.run(&resources.as_resources_ref(), &mut world, &executor);
+    assert_eq!(*resources.get::<i32>(), 14);
+}
+
+#[test]
+fn group_errors() {
+    #[system]
+    fn fail(x: &mut i32) -> Result<(), std::num::ParseIntError> {
+        *x += 1;
+        "not a number".parse::<i32>()?;
+        Ok(())
+    }
+
+    #[system]
+    fn after_fail(x: &mut i32) {
+        *x += 10;
+    }
+
+    #[system]
+    fn after_group(x: &mut i32) {
+        *x += 100;
+    }
+
+    let group = || SystemGroup::new("group").with(fail).with(after_fail);
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    let executor = Executor::new().with(group()).with(after_group);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 111);
+
+    let executor = Executor::new()
+        .with_error_policy(ErrorPolicy::AbortTick)
+        .with(group())
+        .with(after_group);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 112);
+}
+
+#[test]
+fn remove_and_replace() {
+    #[system(label = "add")]
+    fn add_one(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn add_ten(x: &mut i32) {
+        *x += 10;
+    }
+
+    #[system]
+    fn double(x: &mut i32) {
+        *x *= 2;
+    }
+
+    let mut executor = Executor::new().with(add_one).with(double);
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    assert_eq!(
+        executor.replace("add", add_ten).unwrap().name(),
+        "system::add_one"
+    );
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 20);
+
+    assert!(executor.remove("add_ten").is_some());
+    assert!(executor.remove("add_ten").is_none());
+    assert_eq!(executor.num_systems(), 1);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 40);
+}
+
+#[test]
+fn command_buffer() {
+    #[system]
+    fn spawner(commands: &mut CommandBuffer) {
+        commands.spawn(EntityBuilder::new().with(5i32));
+    }
+
+    #[system]
+    fn counter(world: &mut World, count: &mut usize) {
+        *count = world.query::<&i32>().count();
+    }
+
+    let executor = Executor::new().with(spawner).with(counter);
+    let resources = OwnedResources::new().with(0usize);
+    let mut world = World::new();
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 1);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 2);
+}
+
+#[test]
+fn async_system() {
+    struct SpawnLater;
+
+    impl AsyncSystem for SpawnLater {
+        fn start(&self, resources: &ResourcesEnum, _world: &World) -> BoxFuture<CommandBuffer> {
+            let value = *resources.get::<i32>();
+            Box::pin(async move {
+                let mut commands = CommandBuffer::new();
+                commands.spawn(EntityBuilder::new().with(value));
+                commands
+            })
+        }
+    }
+
+    #[system]
+    fn counter(world: &mut World, count: &mut usize) {
+        *count = world.query::<&i32>().count();
+    }
+
+    let resources = OwnedResources::new().with(0usize).with(5i32);
+    let mut world = World::new();
+
+    // Joined within the tick.
+    let executor = Executor::new()
+        .with_async(Stage::First, Stage::Update, SpawnLater)
+        .with_stage(Stage::Last, counter);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 1);
+
+    // Joined during the following tick.
+    let executor = Executor::new()
+        .with_async(Stage::Update, Stage::First, SpawnLater)
+        .with_stage(Stage::Last, counter);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 1);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 2);
+}
+
+#[test]
+fn groups() {
+    #[system]
+    fn add_one(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn add_two(x: &mut i32) {
+        *x += 2;
+    }
+
+    #[system(before = "physics")]
+    fn double(x: &mut i32) {
+        *x *= 2;
+    }
+
+    let mut executor = Executor::new();
+    executor.add_group(
+        "physics",
+        vec![Box::new(add_one) as Box<dyn RawSystem>, Box::new(add_two)],
+    );
+    executor.add(double);
+
+    let mut resources = OwnedResources::new().with(1i32);
+    let mut world = World::new();
+    executor.set_up(&mut resources, &mut world);
+    assert_eq!(executor.num_systems(), 2);
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 5);
+
+    assert!(executor.set_enabled("physics", false));
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 10);
+}
+
+#[test]
+fn qualified_names() {
+    mod physics {
+        use fecs::system;
+
+        #[system]
+        pub fn update(x: &mut i32) {
+            *x += 1;
+        }
+    }
+
+    mod ai {
+        use fecs::system;
+
+        #[system]
+        pub fn update(x: &mut i32) {
+            *x += 10;
+        }
+    }
+
+    let executor = Executor::new().with(physics::update).with(ai::update);
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    let names: Vec<_> = executor.systems().map(|info| info.name).collect();
+    assert_eq!(names, vec!["system::physics::update", "system::ai::update"]);
+
+    assert!(executor.set_enabled("ai::update", false));
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 1);
+
+    assert!(executor.set_enabled("update", false));
+    assert_eq!(executor.is_enabled("system::physics::update"), Some(false));
+    assert!(!executor.set_enabled("date", true));
+}
+
+#[test]
+fn introspection() {
+    #[system]
+    fn first() {}
+
+    #[system]
+    fn second() {}
+
+    let executor = Executor::new().with(second).with_stage(Stage::First, first);
+    executor.set_enabled("second", false);
+
+    let systems: Vec<_> = executor
+        .systems()
+        .map(|info| (info.index, info.name, info.enabled, info.stage))
+        .collect();
+    assert_eq!(
+        systems,
+        vec![
+            (0, "system::first", true, Stage::First),
+            (1, "system::second", false, Stage::Update)
+        ]
+    );
+}
+
+#[cfg(feature = "dylib")]
+#[test]
+fn load_missing_library() {
+    let mut executor = Executor::new();
+    let result = unsafe {
+        executor.load_library(
+            "does-not-exist.so",
+            &mut OwnedResources::new(),
+            &mut World::new(),
+        )
+    };
+    assert!(matches!(result, Err(fecs::LibraryError::Copy(_))));
+    assert_eq!(executor.num_systems(), 0);
+}
+
+#[cfg(feature = "dylib")]
+#[test]
+fn load_and_reload_library() {
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+    #[system(after = "library")]
+    fn double(x: &mut i32) {
+        *x *= 2;
+    }
+
+    // Built from `examples/system_library.rs` and
+    // `examples/system_library_v2.rs` by `cargo test`.
+    let examples = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .with_file_name("examples");
+    let library = |name: &str| examples.join(format!("{}{}{}", DLL_PREFIX, name, DLL_SUFFIX));
+    let path =
+        std::env::temp_dir().join(format!("fecs-reload-{}{}", std::process::id(), DLL_SUFFIX));
+    std::fs::copy(library("system_library"), &path).unwrap();
+
+    let mut executor = Executor::new().with(double);
+    let mut resources = OwnedResources::new().with(1i32);
+    let mut world = World::new();
+    executor.set_up(&mut resources, &mut world);
+
+    let id = unsafe { executor.load_library(&path, &mut resources, &mut world) }.unwrap();
+    let names: Vec<_> = executor.systems().map(|info| info.name).collect();
+    assert_eq!(
+        names,
+        vec!["system_library::library_system", "system::double"]
+    );
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 4);
+
+    unsafe { executor.reload_library(id, &mut resources, &mut world) }.unwrap();
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 10);
+
+    // Swapping the file changes the behavior on the next reload.
+    std::fs::copy(library("system_library_v2"), &path).unwrap();
+    unsafe { executor.reload_library(id, &mut resources, &mut world) }.unwrap();
+    let names: Vec<_> = executor.systems().map(|info| info.name).collect();
+    assert_eq!(
+        names,
+        vec!["system_library_v2::library_system", "system::double"]
+    );
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 220);
+
+    executor.unload_library(id).unwrap();
+    assert_eq!(executor.num_systems(), 1);
+    assert!(matches!(
+        executor.unload_library(id),
+        Err(fecs::LibraryError::NotLoaded(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sub_executor() {
+    struct Cutscene;
+
+    #[system]
+    fn add_one(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn run_cutscene(
+        cutscene: &SubExecutor<Cutscene>,
+        resources: &ResourcesEnum,
+        world: &mut World,
+    ) {
+        cutscene.execute(resources, world);
+        cutscene.execute(resources, world);
+    }
+
+    let mut resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+    SubExecutor::<Cutscene>::new(Executor::new().with(add_one))
+        .insert_into(&mut resources, &mut world);
+
+    let executor = Executor::new().with(run_cutscene);
+    assert_eq!(run_cutscene.access(), None);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn chunked() {
+    #[system]
+    fn first(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn second(y: &mut u32) {
+        *y += 1;
+    }
+
+    #[system]
+    fn third(x: &i32, y: &mut u32) {
+        *y += *x as u32;
+    }
+
+    let mut executor = Executor::new().with(first).with(second).with(third);
+    assert!(executor.set_independent("first", true));
+    assert!(executor.set_independent("second", true));
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    let resources = OwnedResources::new().with(0i32).with(0u32);
+    let mut world = World::new();
+
+    executor.execute_chunked(&resources, &mut world, &pool);
+    executor.execute_chunked(&resources, &mut world, &pool);
+
+    assert_eq!(*resources.get::<i32>(), 2);
+    assert_eq!(*resources.get::<u32>(), 5);
+}
+
+#[test]
+fn tick_and_time() {
+    use std::time::Duration;
+
+    #[system]
+    fn record(tick: &Tick, time: &Time, ticks: &mut Vec<(u64, Duration)>) {
+        ticks.push((tick.0, time.elapsed()));
+    }
+
+    let mut executor = Executor::new().with_manage_time(true).with(record);
+    let mut resources = OwnedResources::new().with(Vec::<(u64, Duration)>::new());
+    let mut world = World::new();
+    executor.set_up(&mut resources, &mut world);
+
+    for _ in 0..3 {
+        executor.execute(&resources, &mut world);
+    }
+
+    let ticks = resources.get::<Vec<(u64, Duration)>>();
+    let numbers: Vec<_> = ticks.iter().map(|(tick, _)| *tick).collect();
+    assert_eq!(numbers, vec![0, 1, 2]);
+    assert_eq!(ticks[0].1, Duration::from_secs(0));
+    assert!(ticks[1].1 <= ticks[2].1);
+}
+
+#[test]
+fn schedule_dot() {
+    #[system(label = "physics")]
+    fn physics(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system(after = "physics")]
+    fn network(x: &i32, world: &World) {
+        let _ = (x, world);
+    }
+
+    let executor = Executor::new().with(network).with(physics);
+    let dot = executor.schedule_dot();
+
+    assert!(dot.starts_with("digraph schedule {"));
+    assert!(dot.contains("subgraph cluster_Update {"));
+    assert!(dot.contains("s0 [label=\"system::network\", fillcolor=lightblue];"));
+    assert!(dot.contains("s1 [label=\"system::physics\\n[physics]\", fillcolor=lightgreen];"));
+    assert!(dot.contains("s1 -> s0;"));
+    assert!(dot.contains("s0 -> s1 [style=dashed, color=red, arrowhead=none];"));
+
+    let executor = Executor::new().with(SystemGroup::new("größe \"x\" \\"));
+    let dot = executor.schedule_dot();
+    assert!(dot.contains("s0 [label=\"größe \\\"x\\\" \\\\\\n[größe \\\"x\\\" \\\\]\""));
+}
+
+#[test]
+fn time_budget() {
+    use std::time::Duration;
+
+    #[system]
+    fn slow() {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    #[system]
+    fn optional(x: &mut i32) {
+        *x += 1;
+    }
+
+    let mut executor = Executor::new().with(slow).with(optional);
+    assert!(executor.set_low_priority("optional", true));
+    let resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 1);
+
+    executor.set_time_budget(Some(Duration::from_millis(1)));
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 1);
+
+    let info = executor.systems().nth(1).unwrap();
+    assert!(info.low_priority);
+    assert_eq!(info.budget_skips, 1);
+}
+
+#[test]
+fn watchdog() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[system]
+    fn slow() {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    let reported = Arc::new(Mutex::new(vec![]));
+    let watchdog = {
+        let reported = Arc::clone(&reported);
+        Watchdog::new(Duration::from_millis(1))
+            .disable_after(2)
+            .on_slow(move |name, _| reported.lock().unwrap().push(name))
+    };
+
+    let mut executor = Executor::new().with(slow);
+    executor.set_watchdog(Some(watchdog));
+    let resources = OwnedResources::new();
+    let mut world = World::new();
+
+    for _ in 0..3 {
+        executor.execute(&resources, &mut world);
+    }
+
+    assert_eq!(
+        *reported.lock().unwrap(),
+        vec!["system::slow", "system::slow"]
+    );
+    assert_eq!(executor.is_enabled("slow"), Some(false));
+}
+
+#[test]
+fn builder_validation() {
+    #[system]
+    fn first(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn second(x: &i32, y: &u32) {
+        let _ = (x, y);
+    }
+
+    let mut resources = OwnedResources::new().with(0i32);
+    let mut world = World::new();
+
+    let errors = ExecutorBuilder::new()
+        .with_independent(first)
+        .with_independent(second)
+        .build(&mut resources, &mut world)
+        .err()
+        .unwrap();
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(
+        errors[0],
+        ScheduleError::MissingResource { system: "system::second", type_id } if type_id == TypeId::of::<u32>()
+    ));
+    assert!(matches!(
+        errors[1],
+        ScheduleError::Conflict {
+            first: "system::first",
+            second: "system::second"
+        }
+    ));
+
+    resources.insert(0u32);
+    let executor = ExecutorBuilder::new()
+        .with(first)
+        .with(second)
+        .build(&mut resources, &mut world)
+        .unwrap();
+    assert_eq!(executor.num_systems(), 2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn deterministic() {
+    macro_rules! spawner {
+        ($name:ident, $value:expr) => {
+            #[system]
+            fn $name(commands: &mut CommandBuffer) {
+                std::thread::sleep(std::time::Duration::from_millis(10 - $value));
+                commands.spawn(EntityBuilder::new().with($value as i32));
+            }
+        };
+    }
+    spawner!(first, 1);
+    spawner!(second, 2);
+    spawner!(third, 3);
+    spawner!(fourth, 4);
+
+    let mut executor = Executor::new()
+        .with(first)
+        .with(second)
+        .with(third)
+        .with(fourth);
+    executor.set_deterministic(true);
+    let resources = OwnedResources::new();
+    let mut world = World::new();
+
+    executor.execute_parallel(&resources, &mut world);
+
+    let values: Vec<i32> = world.query::<&i32>().iter_mut().map(|x| *x).collect();
+    assert_eq!(values, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn apply_config() {
+    use fecs::{ScheduleConfig, SystemConfig};
+
+    #[system]
+    fn add_one(x: &mut i32) {
+        *x += 1;
+    }
+
+    #[system]
+    fn double(x: &mut i32) {
+        *x *= 2;
+    }
+
+    #[system]
+    fn mob_ai(x: &mut i32) {
+        *x += 100;
+    }
+
+    let mut executor = Executor::new().with(add_one).with(double).with(mob_ai);
+
+    let mut config = ScheduleConfig::default();
+    config.systems.insert(
+        "add_one".to_owned(),
+        SystemConfig {
+            after: vec!["double".to_owned()],
+            ..SystemConfig::default()
+        },
+    );
+    config.systems.insert(
+        "mob_ai".to_owned(),
+        SystemConfig {
+            enabled: Some(false),
+            stage: Some(Stage::First),
+            ..SystemConfig::default()
+        },
+    );
+    config
+        .systems
+        .insert("missing".to_owned(), SystemConfig::default());
+
+    let errors = executor.apply_config(&config).unwrap_err();
+    assert!(matches!(&errors[..], [ScheduleError::UnknownSystem { name }] if name == "missing"));
+
+    let systems: Vec<_> = executor
+        .systems()
+        .map(|info| (info.name, info.enabled, info.stage))
+        .collect();
+    assert_eq!(
+        systems,
+        vec![
+            ("system::mob_ai", false, Stage::First),
+            ("system::double", true, Stage::Update),
+            ("system::add_one", true, Stage::Update),
+        ]
+    );
+
+    let resources = OwnedResources::new().with(1i32);
+    executor.execute(&resources, &mut World::new());
+    assert_eq!(*resources.get::<i32>(), 3);
+}
+
+#[test]
+fn optional_resources() {
+    #[system]
+    fn scale(factor: Option<&u32>, value: &mut i32) {
+        *value *= factor.map(|factor| *factor as i32).unwrap_or(1);
+    }
+
+    #[system]
+    fn count(counter: Option<&mut usize>) {
+        if let Some(counter) = counter {
+            *counter += 1;
+        }
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new().with(3i32);
+    let executor = ExecutorBuilder::new()
+        .with(scale)
+        .with(count)
+        .build(&mut resources, &mut world)
+        .unwrap();
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 3);
+
+    resources.insert(2u32);
+    resources.insert(0usize);
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<i32>(), 6);
+    assert_eq!(*resources.get::<usize>(), 1);
+}
+
+#[test]
+fn stage_argument() {
+    #[system(stage = "post_update")]
+    fn broadcast(log: &mut Vec<&'static str>) {
+        log.push("broadcast");
+    }
+
+    #[system(stage = "first")]
+    fn input(log: &mut Vec<&'static str>) {
+        log.push("input");
+    }
+
+    #[system]
+    fn simulate(log: &mut Vec<&'static str>) {
+        log.push("simulate");
+    }
+
+    let executor = Executor::new().with(broadcast).with(simulate).with(input);
+    let stages: Vec<_> = executor.systems().map(|info| info.stage).collect();
+    assert_eq!(stages, vec![Stage::First, Stage::Update, Stage::PostUpdate]);
+
+    let resources = OwnedResources::new().with(Vec::<&'static str>::new());
+    executor.execute(&resources, &mut World::new());
+    assert_eq!(
+        *resources.get::<Vec<&'static str>>(),
+        vec!["input", "simulate", "broadcast"]
+    );
+}
+
+#[test]
+fn state_parameters() {
+    #[system]
+    fn count(#[state] runs: &mut u32, total: &mut u32) {
+        *runs += 1;
+        *total += *runs;
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new().with(0u32);
+    let mut executor = Executor::new().with(count).with(count);
+    executor.set_up(&mut resources, &mut world);
+
+    executor.execute(&resources, &mut world);
+    executor.execute(&resources, &mut world);
+
+    // Each instance keeps its own state: 1 + 1 + 2 + 2.
+    assert_eq!(*resources.get::<u32>(), 6);
+}
+
+#[test]
+fn executor_parameter() {
+    #[system]
+    fn count_systems(executor: &Executor, count: &mut usize) {
+        *count = executor.num_systems();
+    }
+
+    #[system]
+    fn disable_self(executor: &Executor) {
+        executor.set_enabled("disable_self", false);
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new().with(0usize);
+    let mut executor = Executor::new().with(count_systems).with(disable_self);
+    executor.set_up(&mut resources, &mut world);
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 2);
+    assert_eq!(executor.is_enabled("disable_self"), Some(false));
+}
+
+#[test]
+fn setup_function() {
+    struct Greeting(&'static str);
+
+    fn init_greeting(resources: &mut OwnedResources, _world: &mut World) {
+        resources.insert(Greeting("hello"));
+    }
+
+    #[system(setup = "init_greeting")]
+    fn greet(greeting: &Greeting, #[default] count: &mut usize) {
+        *count += greeting.0.len();
+    }
+
+    let mut world = World::new();
+    let mut resources = OwnedResources::new();
+    let mut executor = Executor::new().with(greet);
+    executor.set_up(&mut resources, &mut world);
+
+    executor.execute(&resources, &mut world);
+    assert_eq!(*resources.get::<usize>(), 5);
+}
+
+#[test]
+fn copy_resources() {
+    #[system]
+    fn record(tick: Tick, ticks: &mut Vec<u64>) {
+        ticks.push(tick.0);
+    }
+
+    // `value` is no longer borrowed, so the resource can be written.
+    #[system]
+    fn bump(value: u64, resources: &ResourcesEnum) {
+        *resources.get_mut::<u64>() = value + 1;
+    }
+
+    let mut executor = Executor::new()
+        .with_manage_time(true)
+        .with(record)
+        .with(bump);
+    let mut resources = OwnedResources::new().with(Vec::<u64>::new()).with(10u64);
+    let mut world = World::new();
+    executor.set_up(&mut resources, &mut world);
+
+    executor.execute(&resources, &mut world);
+    executor.execute(&resources, &mut world);
+
+    assert_eq!(*resources.get::<Vec<u64>>(), vec![0, 1]);
+    assert_eq!(*resources.get::<u64>(), 12);
+}
+
+#[test]
+fn cfg_attributes() {
+    // Neither the struct nor its impl may be emitted,
+    // since the missing type would fail to resolve.
+    #[system]
+    #[cfg(any())]
+    fn disabled(_x: &mut DoesNotExist) {}
+
+    #[system]
+    #[cfg(all())]
+    fn enabled(x: &mut i32) {
+        *x += 1;
+    }
+
+    let executor = Executor::new().with(enabled);
+    let resources = OwnedResources::new().with(0i32);
+    executor.execute(&resources, &mut World::new());
+    assert_eq!(*resources.get::<i32>(), 1);
+}
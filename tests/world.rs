@@ -0,0 +1,34 @@
+use fecs::{EntityBuilder, World};
+
+#[test]
+fn despawn_deferred() {
+    let mut world = World::new();
+    let entity = EntityBuilder::new()
+        .with(10i32)
+        .build()
+        .spawn_in(&mut world);
+
+    {
+        let component = world.get::<i32>(entity);
+        world.despawn_deferred(entity);
+        assert_eq!(*component, 10);
+    }
+
+    assert!(world.is_alive(entity));
+    assert_eq!(world.flush_despawns(), 1);
+    assert!(!world.is_alive(entity));
+    assert_eq!(world.flush_despawns(), 0);
+}
+
+#[test]
+fn legion_conversion() {
+    let mut legion_world = fecs::legion::world::World::default();
+    let entity = legion_world.insert((), vec![(10i32,)])[0];
+
+    let mut world = World::from(legion_world);
+    assert_eq!(*world.get::<i32>(entity), 10);
+    *world.get_mut::<i32>(entity) = 11;
+
+    let legion_world: fecs::legion::world::World = world.into();
+    assert_eq!(*legion_world.get_component::<i32>(entity).unwrap(), 11);
+}